@@ -0,0 +1,57 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+// One line of the audit log: who processed which input, with what, and how it
+// went
+#[derive(Debug, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub user: Option<String>,
+    pub input: String,
+    pub bucket: Option<String>,
+    pub job_name: Option<String>,
+    pub model_ids: Vec<String>,
+    pub language_code: Option<String>,
+    pub duration_secs: f64,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    // A record of a run that started `duration_secs` ago, stamped now
+    pub fn new(input: &str, duration_secs: f64) -> Self {
+        Self {
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            input: input.to_string(),
+            bucket: None,
+            job_name: None,
+            model_ids: Vec::new(),
+            language_code: None,
+            duration_secs,
+            success: true,
+            error: None,
+        }
+    }
+}
+
+// Append `record` to the audit log at `path` as a line of JSON, creating the
+// file if needed
+pub fn append(path: &Path, record: &AuditRecord) -> Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Unable to write to the audit log {}", path.display()))
+}
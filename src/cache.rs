@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::settings::TranscribeSettings;
+use crate::transcribe::Transcription;
+use crate::Options;
+
+// Transcripts of local audio files are cached under the SHA-256 of the file's
+// contents and the settings that affect the transcript, so re-running on the
+// same recording (e.g. while iterating on a prompt) skips the upload and
+// Amazon Transcribe.
+pub fn key(path: &Path, opts: &Options, settings: &TranscribeSettings) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Error loading file: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Error loading file: {}", path.display()))?;
+
+    let transcription_settings = format!(
+        "{:?}",
+        (
+            &opts.language_code,
+            &opts.language_options,
+            opts.min_language_confidence,
+            &opts.fallback_language,
+            opts.media_format,
+            (
+                opts.speakers_expected,
+                settings.show_speaker_labels,
+                settings.max_speaker_labels,
+            ),
+            (opts.redact_pii, &settings.pii_entity_types),
+            opts.keep_alternatives,
+            &settings.vocabulary_name,
+            &settings.vocabulary_filter_name,
+            settings.vocabulary_filter_method,
+            settings.default_media_format,
+        )
+    );
+    hasher.update(transcription_settings.as_bytes());
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// The cached transcript for `key`, if there is one. An unreadable entry is
+// treated as missing.
+pub fn load(key: &str) -> Option<Transcription> {
+    let contents = std::fs::read_to_string(entry_path(key)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn store(key: &str, transcription: &Transcription) -> Result<()> {
+    let path = entry_path(key);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create cache directory {}", dir.display()))?;
+    }
+    std::fs::write(&path, serde_json::to_string(transcription)?)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+
+    Ok(())
+}
+
+// distill/transcripts/<key>.json under $XDG_CACHE_HOME (or ~/.cache)
+fn entry_path(key: &str) -> PathBuf {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.cache").as_ref()));
+
+    cache_home
+        .join("distill")
+        .join("transcripts")
+        .join(format!("{}.json", key))
+}
@@ -0,0 +1,58 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+// The clipboard tools to try, in order, with their arguments
+#[cfg(target_os = "macos")]
+const TOOLS: &[(&str, &[&str])] = &[("pbcopy", &[])];
+#[cfg(windows)]
+const TOOLS: &[(&str, &[&str])] = &[("clip", &[])];
+#[cfg(not(any(target_os = "macos", windows)))]
+const TOOLS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+// Copy `text` to the system clipboard with the platform's clipboard tool.
+// Fails if none is installed or none can reach a clipboard, e.g. on a
+// headless machine.
+pub fn copy(text: &str) -> Result<()> {
+    let mut failures = Vec::new();
+    for (program, args) in TOOLS {
+        let child = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                failures.push(format!("{}: {}", program, err));
+                continue;
+            }
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .with_context(|| format!("Unable to write to {}", program))?;
+        }
+        let status = child
+            .wait()
+            .with_context(|| format!("Unable to run {}", program))?;
+        if status.success() {
+            return Ok(());
+        }
+        failures.push(format!("{} exited with {}", program, status));
+    }
+
+    let tools: Vec<&str> = TOOLS.iter().map(|(program, _)| *program).collect();
+    if failures.is_empty() {
+        bail!("No clipboard tool found (tried {})", tools.join(", "));
+    }
+    bail!("No clipboard available ({})", failures.join("; "))
+}
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use aws_config::SdkConfig;
+use aws_sdk_s3::config::ProvideCredentials;
+
+use crate::settings::Settings;
+use crate::{s3, summarize, transcribe};
+
+// The outcome of a single setup check. `hint` suggests how to fix a failure.
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<String>,
+    pub hint: &'static str,
+}
+
+// Verify the AWS setup Distill needs, without an audio file: credentials,
+// S3 access, a reachable bucket, Transcribe permissions and access to the
+// configured Bedrock model.
+// `bedrock_config` is `config` unless bedrock.profile names another profile
+pub async fn run_checks(
+    config: &SdkConfig,
+    bedrock_config: &SdkConfig,
+    settings: &Settings,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let credentials = match config.credentials_provider() {
+        Some(provider) => provider
+            .provide_credentials()
+            .await
+            .map(|_| "credentials found".to_string())
+            .map_err(|err| anyhow!(err)),
+        None => Err(anyhow!("no credentials provider configured")),
+    };
+    checks.push(Check {
+        name: "AWS credentials",
+        result: credentials,
+        hint: "Run `aws configure`, or set AWS_PROFILE or AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY.",
+    });
+
+    checks.push(Check {
+        name: "AWS region",
+        result: match config.region() {
+            Some(region) => Ok(format!(
+                "{} (S3 buckets and Transcribe jobs use the bucket's region)",
+                region
+            )),
+            None => Err(anyhow!("no region configured")),
+        },
+        hint: "Set AWS_REGION, or a region in ~/.aws/config with `aws configure`.",
+    });
+
+    let s3_client = s3::client(config, settings);
+    let buckets = s3::list_buckets(&s3_client).await;
+    checks.push(Check {
+        name: "S3 list buckets",
+        result: match &buckets {
+            Ok(buckets) => Ok(format!("{} bucket(s) found", buckets.len())),
+            Err(err) => Err(anyhow!("{}", err)),
+        },
+        hint: "Grant the s3:ListAllMyBuckets permission.",
+    });
+
+    // The configured bucket, or else the first one available
+    let bucket = settings
+        .aws
+        .s3_bucket_name
+        .clone()
+        .or_else(|| buckets.ok().and_then(|buckets| buckets.into_iter().next()));
+    let bucket_result = match &bucket {
+        Some(bucket) => s3::bucket_region(&s3_client, bucket)
+            .await
+            .map(|region| format!("{} is reachable (region {})", bucket, region)),
+        None => Err(anyhow!("no S3 bucket available")),
+    };
+    checks.push(Check {
+        name: "S3 bucket",
+        result: bucket_result,
+        hint: "Create a bucket, or check aws.s3_bucket_name in config.toml and the s3:GetBucketLocation permission.",
+    });
+
+    let transcribe_client = transcribe::client(config, settings);
+    checks.push(Check {
+        name: "Amazon Transcribe",
+        result: transcribe_client
+            .list_transcription_jobs()
+            .max_results(1)
+            .send()
+            .await
+            .map(|_| "transcription jobs can be listed".to_string())
+            .map_err(|err| anyhow!(err)),
+        hint: "Grant the transcribe:ListTranscriptionJobs permission this check uses, and transcribe:StartTranscriptionJob, transcribe:GetTranscriptionJob and transcribe:DeleteTranscriptionJob for runs.",
+    });
+
+    // Bedrock is called in bedrock.region if set, else the default region
+    let bedrock_region = settings
+        .bedrock
+        .region
+        .clone()
+        .or_else(|| bedrock_config.region().map(|region| region.to_string()))
+        .unwrap_or_default();
+    let bedrock_client = summarize::client(bedrock_config, settings);
+    checks.push(Check {
+        name: "Amazon Bedrock model",
+        result: summarize::check_model_access(&bedrock_client, settings)
+            .await
+            .map(|_| {
+                format!(
+                    "{} can be invoked in {}",
+                    settings.model.model_id, bedrock_region
+                )
+            }),
+        hint: "Request access to the model in the Bedrock console, and grant the bedrock:InvokeModel permission.",
+    });
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{mock_aws, MockHttp, MockResponse};
+
+    fn settings() -> Settings {
+        Settings::load(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml"))
+            .unwrap()
+    }
+
+    // The setup check results by name, as "ok" or the error
+    fn results(checks: &[Check]) -> Vec<(&str, String)> {
+        checks
+            .iter()
+            .map(|check| match &check.result {
+                Ok(_) => (check.name, "ok".to_string()),
+                Err(err) => (check.name, err.to_string()),
+            })
+            .collect()
+    }
+
+    // An account with one bucket, "meetings", on top of `mock_aws`
+    fn account(bedrock: Option<MockResponse>) -> MockHttp {
+        let aws = mock_aws("{}", "Hi");
+        let bedrock = std::sync::Mutex::new(bedrock);
+        MockHttp::new(move |request| {
+            if request.uri.contains("x-id=ListBuckets") {
+                return MockResponse::ok(
+                    "<ListAllMyBucketsResult><Buckets><Bucket><Name>meetings</Name></Bucket></Buckets></ListAllMyBucketsResult>",
+                );
+            }
+            let bedrock = match request.uri.contains("/model/") {
+                true => bedrock.lock().unwrap().take(),
+                false => None,
+            };
+            bedrock.unwrap_or_else(|| aws.respond(request))
+        })
+    }
+
+    #[tokio::test]
+    async fn run_checks_passes_a_working_setup() {
+        let mock = account(None);
+        let config = mock.sdk_config().await;
+        let settings = settings();
+
+        let checks = run_checks(&config, &config, &settings).await;
+
+        assert_eq!(
+            results(&checks),
+            vec![
+                ("AWS credentials", "ok".to_string()),
+                ("AWS region", "ok".to_string()),
+                ("S3 list buckets", "ok".to_string()),
+                ("S3 bucket", "ok".to_string()),
+                ("Amazon Transcribe", "ok".to_string()),
+                ("Amazon Bedrock model", "ok".to_string()),
+            ]
+        );
+        assert_eq!(
+            checks[3].result.as_ref().unwrap(),
+            "meetings is reachable (region us-west-2)"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_checks_reports_each_failure_with_a_hint() {
+        let mock = account(Some(MockResponse::status(
+            403,
+            r#"{"__type": "AccessDeniedException", "message": "You don't have access to the model"}"#,
+        )));
+        let config = mock.sdk_config().await;
+        let settings = settings();
+
+        let checks = run_checks(&config, &config, &settings).await;
+
+        let failed: Vec<&Check> = checks
+            .iter()
+            .filter(|check| check.result.is_err())
+            .collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].name, "Amazon Bedrock model");
+        assert!(failed[0].hint.contains("bedrock:InvokeModel"));
+    }
+}
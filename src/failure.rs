@@ -0,0 +1,97 @@
+use std::fmt;
+
+// The kinds of failure the CLI reports with their own exit code, so that
+// wrapper scripts can tell them apart without parsing the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    // A missing or invalid config.toml, profile or prompt
+    Config,
+    // Missing credentials, or a permission AWS denied
+    AwsAccess,
+    Transcription,
+    Summarization,
+    // Reading the audio or writing an output failed
+    Io,
+}
+
+impl FailureKind {
+    // 1 is left for other failures, and 2 for invalid arguments
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Config => 3,
+            FailureKind::AwsAccess => 4,
+            FailureKind::Transcription => 5,
+            FailureKind::Summarization => 6,
+            FailureKind::Io => 7,
+        }
+    }
+
+    // The code reported with --json-errors
+    pub fn code(self) -> &'static str {
+        match self {
+            FailureKind::Config => "config",
+            FailureKind::AwsAccess => "aws_access",
+            FailureKind::Transcription => "transcription",
+            FailureKind::Summarization => "summarization",
+            FailureKind::Io => "io",
+        }
+    }
+}
+
+// An error tagged with the kind of failure it is. It displays exactly like
+// the error it wraps.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+// Tag an error with its kind, e.g. `.map_err(tag(FailureKind::Config))?`
+pub fn tag(kind: FailureKind) -> impl FnOnce(anyhow::Error) -> anyhow::Error {
+    move |error| anyhow::Error::new(Failure { kind, error })
+}
+
+// AWS error codes for missing, invalid or expired credentials and denied
+// permissions, as they appear in the SDK's errors
+const AWS_ACCESS_ERRORS: &[&str] = &[
+    "AccessDenied",
+    "UnrecognizedClientException",
+    "InvalidAccessKeyId",
+    "InvalidClientTokenId",
+    "SignatureDoesNotMatch",
+    "ExpiredToken",
+    "CredentialsNotLoaded",
+];
+
+// The kind of failure `err` is. AWS access errors are recognized wherever
+// they happen; otherwise the error's tag is used, and untagged I/O errors are
+// reported as such. None for anything else.
+pub fn kind_of(err: &anyhow::Error) -> Option<FailureKind> {
+    let aws_access = err.chain().any(|cause| {
+        let debug = format!("{:?}", cause);
+        AWS_ACCESS_ERRORS.iter().any(|code| debug.contains(code))
+    });
+    if aws_access {
+        return Some(FailureKind::AwsAccess);
+    }
+
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<Failure>().map(|failure| failure.kind))
+        .or_else(|| {
+            err.chain()
+                .any(|cause| cause.is::<std::io::Error>())
+                .then_some(FailureKind::Io)
+        })
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+// Run the post-processing `command` through the shell with `path` as its last
+// argument, e.g. "./publish.sh --draft" runs `./publish.sh --draft
+// summary.docx`. Its output goes to the terminal; a non-zero exit status is an
+// error.
+pub fn run_post_hook(command: &str, path: &Path) -> Result<()> {
+    #[cfg(windows)]
+    let status = Command::new("cmd")
+        .arg("/C")
+        .arg(command)
+        .arg(path)
+        .status();
+    // "$@" appends the path as a single argument, whatever characters it has
+    #[cfg(not(windows))]
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg("sh")
+        .arg(path)
+        .status();
+
+    let status = status.with_context(|| format!("Unable to run post hook '{}'", command))?;
+    if !status.success() {
+        bail!(
+            "Post hook '{}' failed for {} ({})",
+            command,
+            path.display(),
+            status
+        );
+    }
+
+    Ok(())
+}
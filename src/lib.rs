@@ -0,0 +1,1108 @@
+//! Distill summarizes an audio file (e.g., a meeting) using Amazon Transcribe
+//! and Amazon Bedrock. The `distill-cli` binary is a thin wrapper around
+//! [`Distiller`], which can also be embedded in other Rust programs.
+
+pub mod audit;
+pub mod cache;
+pub mod clipboard;
+pub mod doctor;
+pub mod failure;
+pub mod hook;
+pub mod notify;
+pub mod output;
+pub mod progress;
+pub mod redact;
+pub mod s3;
+pub mod settings;
+pub mod summarize;
+pub mod transcribe;
+pub mod transcript;
+pub mod worker;
+
+// Shared with the integration tests
+#[cfg(test)]
+mod test_util;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use aws_config::{Region, SdkConfig};
+use tracing::debug;
+
+pub use failure::FailureKind;
+pub use output::OutputType;
+pub use progress::Progress;
+pub use settings::Settings;
+pub use summarize::ModelSummary;
+pub use transcribe::InputMediaFormat;
+pub use transcript::{EmptyTranscriptAction, SpeakerTurn};
+
+/// The audio to distill.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// A local audio file, uploaded to S3 before transcription.
+    File(PathBuf),
+    /// An object already in S3, transcribed in place. `Options::bucket` is
+    /// ignored and the object is never deleted.
+    S3 { bucket: String, key: String },
+}
+
+/// Per-run options.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The S3 bucket the audio is uploaded to.
+    pub bucket: String,
+    /// Key prefix for the uploaded audio, e.g. "distill/incoming/". Empty
+    /// uploads to the bucket root.
+    pub key_prefix: String,
+    /// The outputs to write, e.g. Markdown and Word, from a single
+    /// transcription and summary.
+    pub output_types: Vec<OutputType>,
+    /// Write file-based output here instead of e.g. summary.md in the current
+    /// directory.
+    pub output_file: Option<PathBuf>,
+    /// Language code of the audio, or "auto" to let Transcribe identify it.
+    pub language_code: String,
+    /// With "auto", restrict identification to these language codes, e.g.
+    /// "en-US" and "es-US". Empty considers every supported language.
+    pub language_options: Vec<String>,
+    /// With "auto", the minimum identification confidence (0-1) to accept.
+    pub min_language_confidence: Option<f32>,
+    /// With "auto", the language to re-run with when confidence is too low.
+    pub fallback_language: Option<String>,
+    /// Skip media format detection and use this format instead.
+    pub media_format: Option<InputMediaFormat>,
+    /// What to do when the transcript is shorter than
+    /// `transcribe.min_transcript_words`.
+    pub on_empty_transcript: EmptyTranscriptAction,
+    /// Summarize into exactly these sections, e.g. "Overview". Empty produces
+    /// a free-form summary.
+    pub sections: Vec<String>,
+    /// Summarize with these two models side by side instead of the configured
+    /// model.
+    pub compare_models: Option<(String, String)>,
+    /// Print the summary to the terminal as it is generated.
+    pub stream: bool,
+    /// Write the exact prompt sent to the model to prompt.txt.
+    pub record_prompt: bool,
+    /// The number of people in the recording, used to tighten speaker
+    /// diarization and sanity-check the detected speakers.
+    pub speakers_expected: Option<i32>,
+    /// Prefix for the Transcribe job name, e.g. "myteam".
+    pub job_prefix: Option<String>,
+    /// Keep the Transcribe job instead of deleting it once its transcript has
+    /// been downloaded.
+    pub keep_job: bool,
+    /// Save Amazon Transcribe's JSON output to transcript.json.
+    pub save_raw_transcript: bool,
+    /// Mark words in the written transcription whose confidence is below
+    /// this threshold (0-1), e.g. "[word?]".
+    pub show_confidence: Option<f64>,
+    /// Record up to this many (2-10) alternative transcriptions of each
+    /// segment in JSON output.
+    pub keep_alternatives: Option<i32>,
+    /// Add how long each speaker talked, and their share of the talk time,
+    /// after the summary.
+    pub talk_time: bool,
+    /// Have Amazon Transcribe mask PII in the transcript, e.g. `[PII]` in
+    /// place of a name.
+    pub redact_pii: bool,
+    /// Mask PII in the summary using Amazon Comprehend.
+    pub redact_summary: bool,
+    /// Append a dated section to an existing output file instead of
+    /// overwriting it.
+    pub append: bool,
+    /// Delete the uploaded audio from S3 once it has been transcribed.
+    pub delete_s3_object: bool,
+    /// Summarize the transcription with Bedrock. When off, only the
+    /// transcription is written and Bedrock isn't called.
+    pub summarize: bool,
+    /// Include the transcription in the output. It is still returned in
+    /// `DistillResult`.
+    pub write_transcript: bool,
+    /// Write the result to `output_types`. Callers that handle the returned
+    /// `DistillResult` themselves can turn this off.
+    pub write_output: bool,
+    /// Check the input, bucket and settings and print what would be done,
+    /// without uploading, transcribing or summarizing anything.
+    pub dry_run: bool,
+    /// Reuse the transcript of a local file transcribed earlier with the same
+    /// settings, and cache new transcripts, under ~/.cache/distill.
+    pub cache: bool,
+    /// Show no spinner or progress lines, only the result and any warnings.
+    pub quiet: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            bucket: String::new(),
+            key_prefix: String::new(),
+            output_types: vec![OutputType::Terminal],
+            output_file: None,
+            language_code: "en-US".to_string(),
+            language_options: Vec::new(),
+            min_language_confidence: None,
+            fallback_language: None,
+            media_format: None,
+            on_empty_transcript: EmptyTranscriptAction::Proceed,
+            sections: Vec::new(),
+            compare_models: None,
+            stream: false,
+            record_prompt: false,
+            speakers_expected: None,
+            job_prefix: None,
+            keep_job: false,
+            save_raw_transcript: false,
+            show_confidence: None,
+            keep_alternatives: None,
+            talk_time: false,
+            redact_pii: false,
+            redact_summary: false,
+            append: false,
+            delete_s3_object: false,
+            summarize: true,
+            write_transcript: true,
+            write_output: true,
+            dry_run: false,
+            cache: false,
+            quiet: false,
+        }
+    }
+}
+
+/// The outcome of a successful run.
+#[derive(Debug, Clone)]
+pub struct DistillResult {
+    /// Where the audio was transcribed from. For a cached transcript, where it
+    /// would have been uploaded.
+    pub s3_uri: String,
+    pub transcription: String,
+    /// The transcription's speaker turns, with timings when known.
+    pub turns: Vec<SpeakerTurn>,
+    pub summary: String,
+    /// With `compare_models`, the summary from each model. `summary` is empty
+    /// in that case.
+    pub comparison: Vec<ModelSummary>,
+    /// The files written for file-based output types.
+    pub output_files: Vec<PathBuf>,
+    /// The Amazon Transcribe job that produced the transcript. None for a dry
+    /// run.
+    pub job_name: Option<String>,
+    /// The transcript's language, as detected or given.
+    pub language_code: Option<String>,
+    /// The models that summarized the transcript; empty if it wasn't
+    /// summarized.
+    pub model_ids: Vec<String>,
+}
+
+/// AWS resources created by runs that haven't finished with them yet: uploaded
+/// audio that is still being transcribed, and transcription jobs that haven't
+/// been deleted. An interrupted run cleans these up with
+/// [`Distiller::clean_up_interrupted`].
+#[derive(Debug, Default)]
+pub struct InFlight {
+    objects: Mutex<Vec<(aws_sdk_s3::Client, String, String)>>,
+    jobs: Mutex<Vec<(aws_sdk_transcribe::Client, String)>>,
+}
+
+impl InFlight {
+    fn add_object(&self, client: &aws_sdk_s3::Client, bucket: &str, key: &str) {
+        self.objects
+            .lock()
+            .unwrap()
+            .push((client.clone(), bucket.to_string(), key.to_string()));
+    }
+
+    fn remove_object(&self, bucket: &str, key: &str) {
+        self.objects
+            .lock()
+            .unwrap()
+            .retain(|(_, b, k)| (b.as_str(), k.as_str()) != (bucket, key));
+    }
+
+    fn add_job(&self, client: &aws_sdk_transcribe::Client, job_name: &str) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .push((client.clone(), job_name.to_string()));
+    }
+
+    fn remove_job(&self, job_name: &str) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|(_, name)| name != job_name);
+    }
+}
+
+/// Runs the upload → transcribe → summarize → output pipeline.
+pub struct Distiller {
+    s3: aws_sdk_s3::Client,
+    transcribe: aws_sdk_transcribe::Client,
+    bedrock: aws_sdk_bedrockruntime::Client,
+    comprehend: aws_sdk_comprehend::Client,
+    settings: Settings,
+    bedrock_limiter: summarize::BedrockLimiter,
+    // Regional S3 and Transcribe clients, built once per bucket region
+    regional: Mutex<HashMap<Region, (aws_sdk_s3::Client, aws_sdk_transcribe::Client)>>,
+    in_flight: InFlight,
+}
+
+impl Distiller {
+    pub fn new(config: SdkConfig, settings: Settings) -> Self {
+        Self::with_bedrock_config(&config, &config, settings)
+    }
+
+    /// Like [`Distiller::new`], but calls Amazon Bedrock with `bedrock_config`,
+    /// e.g. credentials for a different account.
+    pub fn with_bedrock_config(
+        config: &SdkConfig,
+        bedrock_config: &SdkConfig,
+        settings: Settings,
+    ) -> Self {
+        Self::from_clients(
+            s3::client(config, &settings),
+            transcribe::client(config, &settings),
+            summarize::client(bedrock_config, &settings),
+            aws_sdk_comprehend::Client::new(config),
+            settings,
+        )
+    }
+
+    /// Build a `Distiller` from pre-configured clients, e.g. clients backed by
+    /// a mock HTTP client in tests.
+    pub fn from_clients(
+        s3: aws_sdk_s3::Client,
+        transcribe: aws_sdk_transcribe::Client,
+        bedrock: aws_sdk_bedrockruntime::Client,
+        comprehend: aws_sdk_comprehend::Client,
+        settings: Settings,
+    ) -> Self {
+        Self {
+            s3,
+            transcribe,
+            bedrock,
+            comprehend,
+            bedrock_limiter: summarize::BedrockLimiter::new(
+                settings.bedrock.max_concurrent,
+                settings.bedrock.requests_per_minute,
+            ),
+            settings,
+            regional: Mutex::new(HashMap::new()),
+            in_flight: InFlight::default(),
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Write `body` to `key` in `bucket`, e.g. to store a summary next to the
+    /// recording it came from.
+    pub async fn put_text(&self, bucket: &str, key: &str, body: String) -> Result<()> {
+        let region = s3::bucket_region(&self.s3, bucket).await?;
+        let (regional_s3_client, _) = self.regional_clients(region);
+
+        s3::put_text(&regional_s3_client, bucket, key, body, &self.settings).await
+    }
+
+    /// Upload the file at `path` to `key` in `bucket`, e.g. to keep a written
+    /// summary once the machine that produced it is gone.
+    pub async fn put_file(&self, bucket: &str, key: &str, path: &Path) -> Result<()> {
+        let region = s3::bucket_region(&self.s3, bucket).await?;
+        let (regional_s3_client, _) = self.regional_clients(region);
+
+        s3::upload_file(&regional_s3_client, bucket, key, path, &self.settings, None).await
+    }
+
+    /// Delete the uploaded audio and transcription jobs of runs that were
+    /// interrupted, e.g. with Ctrl-C, so they aren't left behind (and billed
+    /// for). Failures are reported but not returned.
+    pub async fn clean_up_interrupted(&self) {
+        let objects = std::mem::take(&mut *self.in_flight.objects.lock().unwrap());
+        for (client, bucket, key) in objects {
+            if let Err(err) = s3::delete_object(&client, &bucket, &key).await {
+                eprintln!("⚠️  Unable to delete s3://{}/{}: {:#}", bucket, key, err);
+            }
+        }
+
+        let jobs = std::mem::take(&mut *self.in_flight.jobs.lock().unwrap());
+        for (client, job_name) in jobs {
+            transcribe::delete_job(&client, &job_name).await;
+        }
+    }
+
+    // For --dry-run: check the language settings and print what `run` would
+    // do with the audio at `key`, without uploading, transcribing or
+    // summarizing anything
+    fn print_plan(
+        &self,
+        mut spinner: Progress,
+        bucket: &str,
+        region: &Region,
+        key: &str,
+        media_format: &aws_sdk_transcribe::types::MediaFormat,
+        opts: &Options,
+    ) -> Result<DistillResult> {
+        spinner.clear();
+        match opts.language_code.as_str() {
+            "auto" => {
+                for code in &opts.language_options {
+                    transcribe::parse_language_code(code)?;
+                }
+            }
+            code => {
+                transcribe::parse_language_code(code)?;
+            }
+        }
+        if let Some(code) = &opts.fallback_language {
+            transcribe::parse_language_code(code)?;
+        }
+
+        let models = match &opts.compare_models {
+            _ if !opts.summarize => "none (not summarizing)".to_string(),
+            Some((model_a, model_b)) => format!("{} and {}", model_a, model_b),
+            None => self.settings.model.model_id.clone(),
+        };
+        let output_types: Vec<String> = opts
+            .output_types
+            .iter()
+            .map(|output_type| format!("{:?}", output_type).to_lowercase())
+            .collect();
+
+        println!();
+        println!("🔎 Dry run: nothing was uploaded, transcribed or summarized");
+        println!(
+            "   Audio:        s3://{}/{} ({})",
+            bucket,
+            key,
+            media_format.as_str()
+        );
+        println!("   Region:       {}", region);
+        println!(
+            "   Job name:     {}",
+            transcribe::job_name(opts.job_prefix.as_deref(), "<id>")
+        );
+        println!("   Language:     {}", opts.language_code);
+        println!("   Model:        {}", models);
+        println!("   Output:       {}", output_types.join(", "));
+
+        Ok(DistillResult {
+            s3_uri: format!("s3://{}/{}", bucket, key),
+            transcription: String::new(),
+            turns: Vec::new(),
+            summary: String::new(),
+            comparison: Vec::new(),
+            output_files: Vec::new(),
+            job_name: None,
+            language_code: None,
+            model_ids: Vec::new(),
+        })
+    }
+
+    pub async fn run(&self, input: Input, opts: Options) -> Result<DistillResult> {
+        if opts.compare_models.is_some() && opts.append {
+            bail!("Appending to an existing output file is not supported when comparing models");
+        }
+
+        if opts.stream && opts.compare_models.is_some() {
+            bail!("Streaming the summary is not supported when comparing models");
+        }
+
+        if opts.stream && opts.redact_summary {
+            bail!("Streaming the summary is not supported with --redact-summary, as it would be printed before it is redacted");
+        }
+
+        if opts.talk_time && opts.compare_models.is_some() {
+            bail!("Talk time is not supported when comparing models");
+        }
+
+        if !opts.summarize && opts.compare_models.is_some() {
+            bail!("Comparing models requires summarizing the transcription");
+        }
+
+        if !opts.summarize && !opts.write_transcript {
+            bail!("There is nothing to write without both the summary and the transcription");
+        }
+
+        let subtitles = opts
+            .output_types
+            .iter()
+            .any(|output_type| matches!(output_type, OutputType::Vtt | OutputType::Srt));
+        if !opts.write_transcript && subtitles {
+            bail!("Subtitle output is the transcription, so it can't leave the transcription out");
+        }
+
+        if !opts.summarize && opts.output_types.contains(&OutputType::Slack) {
+            bail!("Slack output posts the summary, so it requires summarizing the transcription");
+        }
+
+        if opts.append && opts.output_types.contains(&OutputType::Pdf) {
+            bail!("Appending to an existing output file is not supported for PDF output");
+        }
+
+        if opts.append && opts.output_types.contains(&OutputType::Json) {
+            bail!("Appending to an existing output file is not supported for JSON output");
+        }
+
+        if let Some(threshold) = opts.show_confidence {
+            if !(0.0..=1.0).contains(&threshold) {
+                bail!(
+                    "Invalid confidence threshold {}: must be between 0 and 1",
+                    threshold
+                );
+            }
+        }
+
+        if let Some(prefix) = &opts.job_prefix {
+            transcribe::validate_job_prefix(prefix)?;
+        }
+
+        if let Some(output_file) = &opts.output_file {
+            output::check_output_file(output_file)?;
+        }
+
+        let bucket = match &input {
+            Input::File(_) => opts.bucket.clone(),
+            Input::S3 { bucket, .. } => bucket.clone(),
+        };
+
+        let mut spinner = Progress::new("Uploading file to S3...", opts.quiet);
+
+        // A transcript cached from an earlier run on the same audio skips the
+        // upload and transcription
+        let cache_key = match &input {
+            Input::File(path) if opts.cache && !opts.dry_run => {
+                let path = shellexpand::tilde(&path.to_string_lossy()).to_string();
+                Path::new(&path)
+                    .exists()
+                    .then(|| cache::key(Path::new(&path), &opts, &self.settings.transcribe))
+                    .transpose()?
+            }
+            _ => None,
+        };
+        let cached = cache_key.as_deref().and_then(cache::load);
+
+        let (s3_uri, file_name, transcribed) = match (cached, input) {
+            (Some(transcribed), Input::File(path)) => {
+                let file_name = path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                let key = s3::object_key(&opts.key_prefix, &file_name);
+                spinner.note("");
+                spinner.update("Using cached transcript (--no-cache to transcribe again)");
+
+                (format!("s3://{}/{}", bucket, key), file_name, transcribed)
+            }
+            (_, input) => {
+                // Load the bucket region and create a new client to use that
+                // region
+                let region = s3::bucket_region(&self.s3, &bucket).await?;
+                debug!("Bucket {} is in region {}", bucket, region);
+                spinner.note("");
+                spinner.update(format!("Using bucket region {}", region));
+                let (regional_s3_client, regional_transcribe_client) =
+                    self.regional_clients(region.clone());
+
+                let uploaded = matches!(input, Input::File(_));
+                let (key, media_format) = match input {
+                    Input::File(input_audio_file) => {
+                        // Handle conversion of relative paths to absolute paths
+                        let file_path = input_audio_file.as_path();
+                        let file_name = file_path
+                            .file_name()
+                            .unwrap()
+                            .to_string_lossy()
+                            .into_owned();
+
+                        let absolute_path =
+                            shellexpand::tilde(file_path.to_str().unwrap()).to_string();
+                        let absolute_path = Path::new(&absolute_path);
+
+                        if !absolute_path.exists() {
+                            bail!("\nThe path {} does not exist.", absolute_path.display());
+                        }
+
+                        let media_format = transcribe::check_input_file(
+                            absolute_path,
+                            opts.media_format,
+                            self.settings.transcribe.default_media_format,
+                        )?;
+                        if let Some(warning) =
+                            transcribe::limits_warning(absolute_path, &media_format)
+                        {
+                            spinner.warn(&warning);
+                            spinner.update("Uploading file to S3...");
+                        }
+
+                        let canonicalized_path = absolute_path.canonicalize()?;
+                        let key = s3::object_key(&opts.key_prefix, &file_name);
+                        if opts.dry_run {
+                            return self.print_plan(
+                                spinner,
+                                &bucket,
+                                &region,
+                                &key,
+                                &media_format,
+                                &opts,
+                            );
+                        }
+                        s3::upload_file(
+                            &regional_s3_client,
+                            &bucket,
+                            &key,
+                            &canonicalized_path,
+                            &self.settings,
+                            Some(&mut spinner),
+                        )
+                        .await?;
+                        // Until it is transcribed, an interrupted run deletes
+                        // the audio
+                        self.in_flight
+                            .add_object(&regional_s3_client, &bucket, &key);
+
+                        (key, media_format)
+                    }
+                    Input::S3 { key, .. } => {
+                        let media_format = transcribe::media_format_from_key(
+                            &key,
+                            opts.media_format,
+                            self.settings.transcribe.default_media_format,
+                        )?;
+                        if opts.dry_run {
+                            return self.print_plan(
+                                spinner,
+                                &bucket,
+                                &region,
+                                &key,
+                                &media_format,
+                                &opts,
+                            );
+                        }
+
+                        (key, media_format)
+                    }
+                };
+                // The object's name without any "folder" prefix, e.g.
+                // "meeting.mp3"
+                let file_name = key.rsplit('/').next().unwrap_or(&key).to_string();
+
+                let s3_uri = format!("s3://{}/{}", bucket, key);
+
+                spinner.note("");
+                spinner.update("Summarizing text...");
+
+                // Transcribe the audio
+                let transcribed = transcribe::transcribe_audio(
+                    &regional_transcribe_client,
+                    &media_format,
+                    &s3_uri,
+                    &mut spinner,
+                    &opts,
+                    &self.settings,
+                    &self.in_flight,
+                )
+                .await;
+                // Once transcribed, the audio stays in S3 like that of a
+                // finished run unless it is deleted below
+                self.in_flight.remove_object(&bucket, &key);
+                let transcribed = transcribed.map_err(failure::tag(FailureKind::Transcription))?;
+
+                // The audio isn't needed once it's transcribed, so delete it
+                // now rather than leave it behind if a later step fails.
+                // Objects that were already in S3 belong to the caller and
+                // are kept.
+                if opts.delete_s3_object && uploaded {
+                    if let Err(err) = s3::delete_object(&regional_s3_client, &bucket, &key).await {
+                        eprintln!("\n⚠️  Unable to delete {}: {:#}", s3_uri, err);
+                    }
+                }
+                if let Some(cache_key) = &cache_key {
+                    if let Err(err) = cache::store(cache_key, &transcribed) {
+                        eprintln!("\n⚠️  Unable to cache the transcript: {:#}", err);
+                    }
+                }
+
+                (s3_uri, file_name, transcribed)
+            }
+        };
+        let turns = transcribed.turns.clone();
+        let transcription = transcript::transcript_text(&turns);
+
+        // Subtitle output is the transcript only, so there's nothing to
+        // summarize unless another output needs it
+        let mut summarize = opts.summarize
+            && opts
+                .output_types
+                .iter()
+                .any(|output_type| !matches!(output_type, OutputType::Vtt | OutputType::Srt));
+
+        // Catch silent failures (bad audio, wrong language) before summarizing
+        // a handful of words into a meaningless blurb
+        let word_count = transcript::word_count(&transcription);
+        let min_words = self.settings.transcribe.min_transcript_words;
+        if summarize && word_count < min_words {
+            match opts.on_empty_transcript {
+                EmptyTranscriptAction::Abort => {
+                    spinner.fail("Transcript is too short");
+                    bail!(
+                        "\nThe transcript has only {} word(s) (minimum {}). Check the audio and language code.",
+                        word_count,
+                        min_words
+                    );
+                }
+                EmptyTranscriptAction::Skip => {
+                    spinner.warn(&format!(
+                        "Transcript has only {} word(s) (minimum {}); skipping summarization",
+                        word_count, min_words
+                    ));
+                    summarize = false;
+                }
+                EmptyTranscriptAction::Proceed => {
+                    spinner.warn(&format!(
+                        "Transcript has only {} word(s) (minimum {}); the summary may be meaningless",
+                        word_count, min_words
+                    ));
+                }
+            }
+        }
+
+        // Summarize the transcription
+        let mut summary = String::new();
+        let mut comparison = Vec::new();
+        match &opts.compare_models {
+            Some((model_a, model_b)) if summarize => {
+                spinner.update(format!("Summarizing with {} and {}...", model_a, model_b));
+                let (summary_a, summary_b) = tokio::try_join!(
+                    self.summarize(model_a, &transcription, &opts.sections, &spinner),
+                    self.summarize(model_b, &transcription, &opts.sections, &spinner),
+                )
+                .map_err(failure::tag(FailureKind::Summarization))?;
+                comparison = vec![summary_a, summary_b];
+            }
+            None if summarize && opts.stream => {
+                // The spinner would garble the streamed text
+                spinner.clear();
+                println!("\nSummary:");
+                summary = self
+                    .stream_summary(&transcription, &opts.sections, &spinner)
+                    .await
+                    .map_err(failure::tag(FailureKind::Summarization))?
+                    .text;
+                spinner.update("Writing output...");
+            }
+            None if summarize => {
+                spinner.update("Summarizing text...");
+                summary = self
+                    .summarize(
+                        &self.settings.model.model_id,
+                        &transcription,
+                        &opts.sections,
+                        &spinner,
+                    )
+                    .await
+                    .map_err(failure::tag(FailureKind::Summarization))?
+                    .text;
+            }
+            _ => {}
+        }
+
+        if opts.redact_summary && summarize {
+            spinner.update_text("Redacting PII from summary...");
+            let entity_types = &self.settings.redaction.summary_entity_types;
+            if comparison.is_empty() {
+                summary = redact::redact_text(
+                    &self.comprehend,
+                    &summary,
+                    &opts.language_code,
+                    entity_types,
+                )
+                .await?;
+            }
+            for model_summary in &mut comparison {
+                model_summary.text = redact::redact_text(
+                    &self.comprehend,
+                    &model_summary.text,
+                    &opts.language_code,
+                    entity_types,
+                )
+                .await?;
+            }
+        }
+
+        // Added after redaction, as speaker labels and durations aren't PII
+        let talk_time = opts
+            .talk_time
+            .then(|| transcript::talk_time_text(&turns))
+            .filter(|talk_time| !talk_time.is_empty());
+        if let Some(talk_time) = talk_time {
+            // A streamed summary has already been printed without it
+            if opts.stream && summarize {
+                println!("\n{}", talk_time);
+            }
+            summary = if summary.is_empty() {
+                talk_time
+            } else {
+                format!("{}\n\n{}", summary, talk_time)
+            };
+        }
+
+        // The summary is written from the unmarked transcription
+        let written_turns = match opts.show_confidence {
+            Some(threshold) => transcript::marked_turns(&turns, threshold),
+            None => turns.clone(),
+        };
+        let written_transcription = match opts.write_transcript {
+            true => transcript::transcript_text(&written_turns),
+            false => String::new(),
+        };
+        let document = output::Document {
+            source_name: &file_name,
+            summary: &summary,
+            transcription: &written_transcription,
+            turns: &written_turns,
+            alternatives: &transcribed.alternatives,
+            job_name: &transcribed.job_name,
+            language_code: transcribed
+                .language_code
+                .as_deref()
+                .unwrap_or(&opts.language_code),
+            model_id: summarize.then_some(self.settings.model.model_id.as_str()),
+        };
+        let mut output_files = Vec::new();
+        if !opts.write_output {
+            spinner.success("Done!");
+        }
+        for (i, &output_type) in opts
+            .output_types
+            .iter()
+            .filter(|_| opts.write_output)
+            .enumerate()
+        {
+            // Each output stops the spinner when it's written
+            if i > 0 {
+                spinner.update("Writing output...");
+            }
+            let output_file = if comparison.is_empty() {
+                output::write_output(output_type, &document, &opts, &self.settings, &mut spinner)
+                    .await?
+            } else {
+                output::write_comparison(
+                    output_type,
+                    output::output_file_for(&opts, output_type).as_deref(),
+                    &comparison,
+                    &written_transcription,
+                    &written_turns,
+                    &self.settings,
+                    &mut spinner,
+                )
+                .await?
+            };
+            output_files.extend(output_file);
+        }
+
+        let model_ids = match &opts.compare_models {
+            _ if !summarize => Vec::new(),
+            Some((model_a, model_b)) => vec![model_a.clone(), model_b.clone()],
+            None => vec![self.settings.model.model_id.clone()],
+        };
+        if opts.record_prompt && summarize {
+            let record =
+                summarize::PromptRecord::new(&self.settings, model_ids.clone(), &opts.sections);
+            let path = output::write_prompt_record(&record)?;
+            spinner.note(&format!("📝 Prompt recorded to {}", path.display()));
+        }
+
+        Ok(DistillResult {
+            s3_uri,
+            transcription,
+            turns,
+            summary,
+            comparison,
+            output_files,
+            job_name: Some(transcribed.job_name),
+            language_code: transcribed.language_code.or(Some(opts.language_code)),
+            model_ids,
+        })
+    }
+
+    // Summarize within the configured Bedrock concurrency and rate limits
+    async fn summarize(
+        &self,
+        model_id: &str,
+        transcription: &str,
+        sections: &[String],
+        spinner: &Progress,
+    ) -> Result<ModelSummary> {
+        let chunks =
+            summarize::chunk_transcript(transcription, self.settings.model.max_input_chars);
+        if chunks.len() == 1 {
+            let _permit = self.bedrock_limiter.acquire().await;
+
+            return summarize::summarize_with_model(
+                &self.bedrock,
+                &self.settings,
+                model_id,
+                transcription,
+                sections,
+            )
+            .await;
+        }
+
+        let parts = self.summarize_parts(model_id, &chunks, spinner).await?;
+        let _permit = self.bedrock_limiter.acquire().await;
+
+        summarize::combine_summaries(&self.bedrock, &self.settings, model_id, &parts, sections)
+            .await
+    }
+
+    // Summarize with the configured model, printing the summary as it is
+    // generated. For a long transcript only the combined summary is streamed.
+    async fn stream_summary(
+        &self,
+        transcription: &str,
+        sections: &[String],
+        spinner: &Progress,
+    ) -> Result<ModelSummary> {
+        let model_id = &self.settings.model.model_id;
+        let chunks =
+            summarize::chunk_transcript(transcription, self.settings.model.max_input_chars);
+        let (prompt, parts) = if chunks.len() == 1 {
+            let prompt = summarize::summary_prompt(&self.settings, sections, transcription);
+            (prompt, Vec::new())
+        } else {
+            let parts = self.summarize_parts(model_id, &chunks, spinner).await?;
+            let prompt = summarize::combined_prompt(&self.settings, sections, &parts);
+            (prompt, parts)
+        };
+        let _permit = self.bedrock_limiter.acquire().await;
+
+        let summary = summarize::stream_with_model(
+            &self.bedrock,
+            &self.settings,
+            model_id,
+            &prompt,
+            sections,
+            &mut std::io::stdout(),
+        )
+        .await?;
+
+        Ok(summarize::with_part_usage(summary, &parts))
+    }
+
+    // Summarize each part of a transcript too long to summarize at once
+    async fn summarize_parts(
+        &self,
+        model_id: &str,
+        chunks: &[String],
+        spinner: &Progress,
+    ) -> Result<Vec<ModelSummary>> {
+        spinner.note(&format!(
+            "📚 Transcript is too long to summarize at once; summarizing it in {} parts",
+            chunks.len()
+        ));
+
+        let mut parts = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let _permit = self.bedrock_limiter.acquire().await;
+            let part = summarize::summarize_part(
+                &self.bedrock,
+                &self.settings,
+                model_id,
+                chunk,
+                i + 1,
+                chunks.len(),
+            )
+            .await?;
+            parts.push(part);
+        }
+
+        Ok(parts)
+    }
+
+    // S3 and Transcribe clients for the bucket's region. These are derived from
+    // the injected clients so any custom configuration carries over, and are
+    // cached so that repeated runs in the same region reuse them.
+    fn regional_clients(&self, region: Region) -> (aws_sdk_s3::Client, aws_sdk_transcribe::Client) {
+        let mut regional = self.regional.lock().unwrap();
+
+        regional
+            .entry(region)
+            .or_insert_with_key(|region| {
+                let s3_config = self.s3.config().to_builder().region(region.clone()).build();
+                let transcribe_config = self
+                    .transcribe
+                    .config()
+                    .to_builder()
+                    .region(region.clone())
+                    .build();
+
+                (
+                    aws_sdk_s3::Client::from_conf(s3_config),
+                    aws_sdk_transcribe::Client::from_conf(transcribe_config),
+                )
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{claude_response, mock_aws, transcript_json, MockHttp};
+
+    fn settings() -> Settings {
+        Settings::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap()
+    }
+
+    // A run on audio already in S3, so nothing is uploaded
+    async fn run(mock: &MockHttp, settings: Settings, opts: Options) -> Result<DistillResult> {
+        let distiller = Distiller::new(mock.sdk_config().await, settings);
+        let input = Input::S3 {
+            bucket: "meetings".to_string(),
+            key: "standup.mp3".to_string(),
+        };
+
+        distiller
+            .run(
+                input,
+                Options {
+                    write_output: false,
+                    quiet: true,
+                    ..opts
+                },
+            )
+            .await
+    }
+
+    fn summarized(mock: &MockHttp) -> bool {
+        mock.requests()
+            .iter()
+            .any(|request| request.uri.contains("/model/"))
+    }
+
+    const SHORT_TRANSCRIPT: &[(&str, &str)] =
+        &[("spk_0", "Can"), ("spk_0", "you"), ("spk_0", "hear")];
+
+    #[tokio::test]
+    async fn a_short_transcript_aborts_with_its_word_count() {
+        let mock = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "Summary");
+        let opts = Options {
+            on_empty_transcript: EmptyTranscriptAction::Abort,
+            ..Options::default()
+        };
+
+        let err = run(&mock, settings(), opts).await.unwrap_err();
+
+        assert!(
+            err.to_string().contains("has only 3 word(s) (minimum 10)"),
+            "{}",
+            err
+        );
+        assert!(!summarized(&mock));
+    }
+
+    #[tokio::test]
+    async fn a_short_transcript_can_skip_summarization() {
+        let mock = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "Summary");
+        let opts = Options {
+            on_empty_transcript: EmptyTranscriptAction::Skip,
+            ..Options::default()
+        };
+
+        let result = run(&mock, settings(), opts).await.unwrap();
+
+        assert_eq!(result.transcription, "spk_0: Can you hear\n");
+        assert_eq!(result.summary, "");
+        assert!(result.model_ids.is_empty());
+        assert!(!summarized(&mock));
+    }
+
+    #[tokio::test]
+    async fn a_short_transcript_is_summarized_by_default() {
+        let mock = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "A sound check.");
+
+        let result = run(&mock, settings(), Options::default()).await.unwrap();
+
+        assert_eq!(result.summary, "A sound check.");
+        assert!(summarized(&mock));
+    }
+
+    #[tokio::test]
+    async fn a_long_enough_transcript_is_summarized_whatever_the_action() {
+        let mock = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "A sound check.");
+        let mut settings = settings();
+        settings.transcribe.min_transcript_words = 3;
+        let opts = Options {
+            on_empty_transcript: EmptyTranscriptAction::Abort,
+            ..Options::default()
+        };
+
+        let result = run(&mock, settings, opts).await.unwrap();
+
+        assert_eq!(result.summary, "A sound check.");
+    }
+
+    #[tokio::test]
+    async fn compare_models_captures_both_summaries() {
+        let aws = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "");
+        let mock = MockHttp::new(move |request| {
+            if request.uri.contains("claude-3-haiku") {
+                claude_response("Haiku's summary")
+            } else if request.uri.contains("claude-3-sonnet") {
+                claude_response("Sonnet's summary")
+            } else {
+                aws.respond(request)
+            }
+        });
+        let haiku = "anthropic.claude-3-haiku-20240307-v1:0";
+        let sonnet = "anthropic.claude-3-sonnet-20240229-v1:0";
+        let opts = Options {
+            compare_models: Some((haiku.to_string(), sonnet.to_string())),
+            ..Options::default()
+        };
+
+        let result = run(&mock, settings(), opts).await.unwrap();
+
+        let summaries: Vec<(&str, &str)> = result
+            .comparison
+            .iter()
+            .map(|summary| (summary.model_id.as_str(), summary.text.as_str()))
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![(haiku, "Haiku's summary"), (sonnet, "Sonnet's summary")]
+        );
+        assert!(result
+            .comparison
+            .iter()
+            .all(|summary| summary.input_tokens == Some(120) && summary.output_tokens == Some(8)));
+        assert_eq!(result.summary, "");
+        assert_eq!(result.model_ids, vec![haiku, sonnet]);
+    }
+
+    #[tokio::test]
+    async fn regional_clients_are_built_once_per_region() {
+        let mock = mock_aws(&transcript_json(SHORT_TRANSCRIPT), "");
+        let distiller = Distiller::new(mock.sdk_config().await, settings());
+
+        let (s3, transcribe) = distiller.regional_clients(Region::new("us-west-2"));
+        distiller.regional_clients(Region::new("us-west-2"));
+        distiller.regional_clients(Region::new("eu-west-1"));
+
+        assert_eq!(s3.config().region(), Some(&Region::new("us-west-2")));
+        assert_eq!(
+            transcribe.config().region(),
+            Some(&Region::new("us-west-2"))
+        );
+        let regional = distiller.regional.lock().unwrap();
+        let mut regions: Vec<&str> = regional.keys().map(|region| region.as_ref()).collect();
+        regions.sort();
+        assert_eq!(regions, vec!["eu-west-1", "us-west-2"]);
+    }
+}
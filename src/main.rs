@@ -1,86 +1,1124 @@
-mod summarize;
-mod transcribe;
-
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use aws_config::default_provider::region::DefaultRegionChain;
 use aws_config::meta::region::RegionProviderChain;
-use aws_config::{Region, SdkConfig};
+use aws_config::SdkConfig;
 use aws_sdk_s3::config::StalledStreamProtectionConfig;
-use clap::Parser;
-use config::{Config, File as ConfigFile};
-use docx_rs::{Docx, Paragraph, Run};
-use reqwest::Client as ReqwestClient;
-use serde_json::json;
-use spinoff::{spinners, Color, Spinner};
-
-use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::Client;
+use clap::{Parser, Subcommand, ValueEnum};
+use distill_cli::s3::{self, list_buckets};
+use distill_cli::summarize::{self, SummaryStyle};
+use distill_cli::transcribe::SUPPORTED_LANGUAGES;
+use distill_cli::{audit, clipboard, doctor, failure, hook, notify, worker};
+use distill_cli::{
+    DistillResult, Distiller, EmptyTranscriptAction, FailureKind, Input, InputMediaFormat, Options,
+    OutputType, Progress, Settings,
+};
+
 use dialoguer::{theme::ColorfulTheme, Select};
+use tracing::debug;
+use uuid::Uuid;
 
 #[derive(Debug, Parser)]
 #[clap(
     about = "Distill CLI can summarize an audio file (e.g., a meeting) using Amazon Transcribe and Amazon Bedrock.",
-    after_help = "For supported languages, consult: https://docs.aws.amazon.com/transcribe/latest/dg/supported-languages.html"
+    after_help = "For supported languages, consult: https://docs.aws.amazon.com/transcribe/latest/dg/supported-languages.html",
+    subcommand_negates_reqs = true
 )]
 struct Opt {
-    #[clap(short, long)]
-    input_audio_file: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// The audio file to summarize, an s3:// URI, an http(s):// URL, or "-" for stdin; repeat for several
+    #[clap(short, long, required_unless_present = "input_dir")]
+    input_audio_file: Vec<String>,
 
+    /// Summarize every audio file in this directory, one after another
+    #[clap(long, value_name = "DIR")]
+    input_dir: Option<PathBuf>,
+
+    /// Where to write the summary; repeat (or separate with commas) for several outputs from one run
     #[clap(
         short,
         long,
         value_enum,
         default_value = "Terminal",
-        ignore_case = true
+        ignore_case = true,
+        value_delimiter = ','
     )]
-    output_type: OutputType,
+    output_type: Vec<OutputType>,
+
+    /// Write the summary to this path instead of summary.<ext> in the current directory. With several output types, each uses its own extension
+    #[clap(long)]
+    output_file: Option<PathBuf>,
 
+    /// Language code of the audio, or "auto" to let Amazon Transcribe identify it
     #[clap(short, long, default_value = "en-US")]
     language_code: String,
 
+    /// Let Amazon Transcribe identify the language (same as "-l auto"), from transcribe.language_options if set
+    #[clap(long, conflicts_with = "language_code")]
+    auto_detect_language: bool,
+
+    /// With "-l auto", the minimum language identification confidence (0-1) to accept
+    #[clap(long)]
+    min_language_confidence: Option<f32>,
+
+    /// With "-l auto", the language code to re-run with when confidence is too low
+    #[clap(long)]
+    fallback_language: Option<String>,
+
+    /// Upload to this S3 bucket instead of aws.s3_bucket_name
+    #[clap(long, value_name = "NAME")]
+    bucket: Option<String>,
+
+    /// Upload under this key prefix, e.g. "distill/incoming/", instead of aws.s3_key_prefix
+    #[clap(long, value_name = "PREFIX")]
+    s3_prefix: Option<String>,
+
+    /// Tag the uploaded audio and the transcription job, in addition to aws.tags; repeat for several
+    #[clap(long, value_name = "KEY=VALUE")]
+    tag: Vec<String>,
+
     #[clap(short, long, default_value = "n")]
     delete_s3_object: String,
+
+    /// Delete the uploaded audio from S3 once it has been transcribed (same as -d Y)
+    #[clap(long)]
+    cleanup: bool,
+
+    /// Skip media format detection and use this format instead
+    #[clap(long, value_enum, ignore_case = true)]
+    media_format: Option<InputMediaFormat>,
+
+    /// What to do when the transcript is shorter than transcribe.min_transcript_words
+    #[clap(long, value_enum, default_value = "proceed")]
+    on_empty_transcript: EmptyTranscriptAction,
+
+    /// Only transcribe: write the transcription without summarizing it with Bedrock
+    #[clap(long, alias = "transcript-only")]
+    no_summarize: bool,
+
+    /// Leave the transcription out of the output and only keep the summary
+    #[clap(long, conflicts_with = "no_summarize")]
+    no_transcript: bool,
+
+    /// Summarize into the sections listed under [sections] in config.toml
+    #[clap(long)]
+    structured_sections: bool,
+
+    /// Summarize into this section; repeat for several. Overrides [sections] in config.toml
+    #[clap(long = "section", value_name = "TITLE")]
+    section: Vec<String>,
+
+    /// Summarize into these standard sections, e.g. "summary,actions,decisions"
+    #[clap(long, value_delimiter = ',', conflicts_with_all = ["section", "structured_sections"])]
+    sections: Vec<StandardSection>,
+
+    /// Summarize with two Bedrock models side by side, e.g. to compare Haiku and Sonnet
+    #[clap(long, num_args = 2, value_names = ["MODEL_ID_A", "MODEL_ID_B"])]
+    compare: Vec<String>,
+
+    /// Ask for a brief TL;DR, detailed notes, or bullet points, on top of the prompt template
+    #[clap(long, value_enum, ignore_case = true)]
+    style: Option<SummaryStyle>,
+
+    /// Use this named template from [prompts] in config.toml instead of prompt.template
+    #[clap(long, value_name = "NAME", conflicts_with = "prompt_file")]
+    prompt: Option<String>,
+
+    /// Use the contents of this file as the prompt template instead of prompt.template
+    #[clap(long)]
+    prompt_file: Option<PathBuf>,
+
+    /// Record the exact prompt sent to the model, and the template's SHA-256, in prompt.txt
+    #[clap(long)]
+    record_prompt: bool,
+
+    /// The number of people in the recording, to improve speaker identification
+    #[clap(long, value_name = "N")]
+    speakers_expected: Option<i32>,
+
+    /// Prefix for the Transcribe job name, e.g. "myteam" for "myteam-transcription-<id>"
+    #[clap(long)]
+    job_prefix: Option<String>,
+
+    /// Keep the Amazon Transcribe job instead of deleting it once the transcript is downloaded
+    #[clap(long)]
+    keep_job: bool,
+
+    /// Save Amazon Transcribe's raw JSON output to transcript.json
+    #[clap(long)]
+    save_raw_transcript: bool,
+
+    /// Mark words in the transcription that Amazon Transcribe is less confident about than this (0-1), e.g. "[word?]"
+    #[clap(long, value_name = "THRESHOLD")]
+    show_confidence: Option<f64>,
+
+    /// Record up to N (2-10) alternative transcriptions of each segment in JSON output
+    #[clap(long, value_name = "N")]
+    keep_alternatives: Option<i32>,
+
+    /// Add how long each speaker talked after the summary, e.g. "spk_0: 12m 30s (45%)"
+    #[clap(long)]
+    talk_time: bool,
+
+    /// Print the summary as it is generated instead of when it is complete
+    #[clap(long)]
+    stream: bool,
+
+    /// Have Amazon Transcribe mask PII (names, SSNs, card numbers, ...) in the transcript
+    #[clap(long)]
+    redact_pii: bool,
+
+    /// Mask PII in the summary using Amazon Comprehend
+    #[clap(long)]
+    redact_summary: bool,
+
+    /// Append a dated section to an existing text, Markdown or Word output instead of overwriting it
+    #[clap(long)]
+    append: bool,
+
+    /// Summarize with this Bedrock model instead of model.model_id, e.g. to try another model
+    #[clap(long, value_name = "ID", conflicts_with = "compare")]
+    model_id: Option<String>,
+
+    /// Override model.temperature
+    #[clap(long)]
+    temperature: Option<f64>,
+
+    /// Override model.top_p
+    #[clap(long)]
+    top_p: Option<f64>,
+
+    /// Override model.top_k
+    #[clap(long)]
+    top_k: Option<i64>,
+
+    /// Use a named profile from config.toml, e.g. [profiles.legal]
+    #[clap(long)]
+    profile_name: Option<String>,
+
+    /// Use named transcription settings from config.toml, e.g. [transcribe.profiles.lecture]
+    #[clap(long, value_name = "NAME")]
+    transcribe_profile: Option<String>,
+
+    /// Email the summary to this address with Amazon SES; repeat for several recipients
+    #[clap(long, value_name = "ADDR")]
+    email_to: Vec<String>,
+
+    /// Also upload the written output files under this S3 location, e.g. s3://my-bucket/summaries/
+    #[clap(long, value_name = "S3_URI")]
+    output_s3: Option<String>,
+
+    /// Show no spinner or progress messages, only the result (nothing for file outputs) and warnings
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Open the written output file in the default application
+    #[clap(long)]
+    open: bool,
+
+    /// Copy the summary (or the transcription, or both) to the clipboard when done
+    #[clap(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "summary")]
+    clipboard: Option<ClipboardContent>,
+
+    /// Run this command on each written output file, with the file's path as its last argument
+    #[clap(long, value_name = "COMMAND")]
+    post_hook: Option<String>,
+
+    /// Post a message with the summary and output location to this Slack incoming webhook when done
+    #[clap(long, value_name = "URL")]
+    slack_webhook: Option<String>,
+
+    /// Only process the input if it was modified since the cutoff (e.g. "24h" or "2024-06-01")
+    #[clap(long)]
+    since: Option<String>,
+
+    /// Transcribe again instead of reusing the cached transcript of a file transcribed before
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Check the input, bucket and settings and print what would happen, without uploading or calling Transcribe or Bedrock
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Fail instead of prompting, e.g. for a bucket (implied when stdin isn't a terminal)
+    #[clap(long)]
+    non_interactive: bool,
+
+    /// Call Amazon Bedrock in this region instead of the default region, e.g. where the model is enabled
+    #[clap(long, global = true)]
+    region: Option<String>,
+
+    /// Use this named AWS profile from ~/.aws/config for credentials and region
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    /// Call Amazon Bedrock with this named AWS profile instead, e.g. for Bedrock in another account
+    #[clap(long, value_name = "NAME", global = true)]
+    bedrock_profile: Option<String>,
+
+    /// Send all AWS requests to this endpoint, e.g. a VPC endpoint or LocalStack
+    #[clap(long, global = true)]
+    endpoint_url: Option<String>,
+
+    /// Read settings from this file instead of ./config.toml (or $XDG_CONFIG_HOME/distill/config.toml)
+    #[clap(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// On failure, print {"code": ..., "message": ...} as JSON to stderr instead of the error text
+    #[clap(long, global = true)]
+    json_errors: bool,
+
+    /// Log each stage (region, bucket, job name, sizes and timings) to stderr; repeat for more detail, -vv includes AWS SDK requests
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
-enum OutputType {
-    Terminal,
-    Text,
-    Word,
-    Markdown,
-    Slack,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Check your AWS setup (credentials, S3, Transcribe and Bedrock access) without an audio file
+    #[clap(alias = "self-test")]
+    Doctor,
+    /// Process recordings from the SQS queue in worker.queue_url as they are uploaded to S3
+    Worker,
+    /// List the language codes supported by --language-code
+    Languages,
+    /// Write a commented default config.toml to the current directory (or the --config path)
+    Init {
+        /// Overwrite an existing config file
+        #[clap(long)]
+        force: bool,
+    },
 }
 
+// Sections that can be picked with --sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StandardSection {
+    Summary,
+    Actions,
+    Decisions,
+}
+
+impl StandardSection {
+    fn title(self) -> &'static str {
+        match self {
+            StandardSection::Summary => "Summary",
+            StandardSection::Actions => summarize::ACTION_ITEMS,
+            StandardSection::Decisions => "Key Decisions",
+        }
+    }
+}
+
+// What --clipboard copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ClipboardContent {
+    Summary,
+    Transcription,
+    Both,
+}
+
+// What to do with each result once it's written
+struct Delivery<'a> {
+    email: Option<&'a (String, Vec<String>)>,
+    output_s3: Option<&'a (String, String)>,
+    open: bool,
+    clipboard: Option<ClipboardContent>,
+    post_hook: Option<&'a str>,
+    slack_webhook: Option<&'a str>,
+    quiet: bool,
+    non_interactive: bool,
+}
+
+impl Delivery<'_> {
+    // Opening files only makes sense for someone sitting at a terminal, so
+    // --open does nothing with --quiet or --non-interactive
+    fn opens_files(&self, terminal: bool) -> bool {
+        self.open && !self.quiet && !self.non_interactive && terminal
+    }
+}
+
+// The commented default config written by `distill init`
+const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+
 #[::tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-    let config = load_config(None).await;
+async fn main() -> ExitCode {
+    let opt = Opt::parse();
+    init_logging(opt.verbose);
+    let json_errors = opt.json_errors;
 
-    let settings = Config::builder()
-        .add_source(ConfigFile::with_name("./config.toml"))
-        .build()?;
+    match distill(opt).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let kind = failure::kind_of(&err);
+            if json_errors {
+                let error = serde_json::json!({
+                    "code": kind.map_or("error", FailureKind::code),
+                    "message": format!("{:#}", err).trim(),
+                });
+                eprintln!("{}", error);
+            } else {
+                eprintln!("Error: {:?}", err);
+            }
+            ExitCode::from(kind.map_or(1, FailureKind::exit_code))
+        }
+    }
+}
 
-    let s3_bucket_name = settings
-        .get_string("aws.s3_bucket_name")
-        .unwrap_or_default();
+// Log to stderr at the level set by -v, or by RUST_LOG without it. The
+// default is errors only, which leaves the spinner output alone.
+fn init_logging(verbose: u8) {
+    let mut builder = env_logger::Builder::from_default_env();
+    match verbose {
+        0 => {}
+        1 => {
+            builder.parse_filters("distill_cli=debug");
+        }
+        // Includes the AWS SDK's own request logging
+        2 => {
+            builder.parse_filters("debug");
+        }
+        _ => {
+            builder.parse_filters("trace");
+        }
+    }
+    builder.init();
+}
+
+async fn distill(opt: Opt) -> Result<()> {
+    if let Some(Command::Languages) = opt.command {
+        print_languages();
+        return Ok(());
+    }
+
+    // There's no config to load yet
+    if let Some(Command::Init { force }) = opt.command {
+        let path = opt.config.unwrap_or_else(|| PathBuf::from("config.toml"));
+        return init_config(&path, force);
+    }
+
+    let config_path = match &opt.config {
+        Some(path) => PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref()),
+        None => Settings::default_path(),
+    };
+    let settings = Settings::load(&config_path).map_err(failure::tag(FailureKind::Config))?;
+
+    let s3_bucket_name = settings.aws.s3_bucket_name.clone().unwrap_or_default();
 
     let Opt {
+        command,
         input_audio_file,
+        input_dir,
         output_type,
+        output_file,
+        language_code,
+        auto_detect_language,
+        min_language_confidence,
+        fallback_language,
+        bucket,
+        s3_prefix,
+        tag,
+        delete_s3_object,
+        cleanup,
+        media_format,
+        on_empty_transcript,
+        no_summarize,
+        no_transcript,
+        structured_sections,
+        sections,
+        section,
+        compare,
+        style,
+        prompt,
+        prompt_file,
+        record_prompt,
+        speakers_expected,
+        job_prefix,
+        keep_job,
+        save_raw_transcript,
+        show_confidence,
+        keep_alternatives,
+        talk_time,
+        stream,
+        redact_pii,
+        redact_summary,
+        append,
+        model_id,
+        temperature,
+        top_p,
+        top_k,
+        profile_name,
+        transcribe_profile,
+        email_to,
+        output_s3,
+        open,
+        clipboard,
+        post_hook,
+        slack_webhook,
+        quiet,
+        since,
+        no_cache,
+        dry_run,
+        non_interactive,
+        region,
+        profile,
+        bedrock_profile,
+        endpoint_url,
+        config: _,
+        json_errors: _,
+        verbose: _,
+    } = opt;
+
+    let config = load_config(endpoint_url.clone(), profile.as_deref()).await;
+    debug!(
+        "Resolved AWS region {}",
+        config.region().map_or("(none)", |region| region.as_ref())
+    );
+
+    let mut settings = match profile_name {
+        Some(name) => settings
+            .with_profile(&name)
+            .map_err(failure::tag(FailureKind::Config))?,
+        None => settings,
+    };
+
+    if let Some(transcribe_profile) = transcribe_profile {
+        settings
+            .use_transcribe_profile(&transcribe_profile)
+            .map_err(failure::tag(FailureKind::Config))?;
+    }
+    if let Some(prompt) = prompt {
+        settings
+            .use_prompt(&prompt)
+            .map_err(failure::tag(FailureKind::Config))?;
+    }
+    if let Some(prompt_file) = prompt_file {
+        settings
+            .load_prompt_file(&prompt_file)
+            .map_err(failure::tag(FailureKind::Config))?;
+    }
+
+    // Command-line overrides take precedence over config.toml and the profile
+    if let Some(model_id) = model_id {
+        settings.model.model_id = model_id;
+    }
+    if let Some(temperature) = temperature {
+        settings.model.temperature = temperature;
+    }
+    if let Some(top_p) = top_p {
+        settings.model.top_p = top_p;
+    }
+    if let Some(top_k) = top_k {
+        settings.model.top_k = top_k;
+    }
+    if let Some(region) = region {
+        settings.bedrock.region = Some(region);
+    }
+    if let Some(bedrock_profile) = bedrock_profile {
+        settings.bedrock.profile = Some(bedrock_profile);
+    }
+    for tag in tag {
+        let (key, value) = parse_tag(&tag)?;
+        settings.aws.tags.insert(key, value);
+    }
+    if let Some(style) = style {
+        settings.prompt.style = Some(style);
+    }
+
+    // The model settings don't matter when nothing is summarized
+    if !no_summarize {
+        settings
+            .validate()
+            .with_context(|| format!("Invalid settings in {}", config_path.display()))
+            .map_err(failure::tag(FailureKind::Config))?;
+    }
+
+    // Bedrock may be in another account, with its own credentials
+    let bedrock_config = match &settings.bedrock.profile {
+        Some(bedrock_profile) => load_config(endpoint_url, Some(bedrock_profile)).await,
+        None => config.clone(),
+    };
+
+    if let Some(Command::Doctor) = command {
+        return run_doctor(&config, &bedrock_config, &settings).await;
+    }
+
+    // Each output is written once, in the order first given
+    let mut output_types: Vec<OutputType> = Vec::new();
+    for output_type in output_type {
+        if !output_types.contains(&output_type) {
+            output_types.push(output_type);
+        }
+    }
+
+    let language_code = if auto_detect_language {
+        "auto".to_string()
+    } else {
+        language_code
+    };
+    let language_options = settings.transcribe.language_options.clone();
+
+    // Explicit --section titles take precedence over the configured list
+    let sections = if !sections.is_empty() {
+        let mut titles: Vec<String> = Vec::new();
+        for section in sections {
+            if !titles.iter().any(|title| title == section.title()) {
+                titles.push(section.title().to_string());
+            }
+        }
+        titles
+    } else if !section.is_empty() {
+        section
+    } else if structured_sections {
+        if settings.sections.titles.is_empty() {
+            bail!("--structured-sections requires titles under [sections] in config.toml");
+        }
+        settings.sections.titles.clone()
+    } else {
+        Vec::new()
+    };
+
+    if let Some(Command::Worker) = command {
+        let sqs_client = aws_sdk_sqs::Client::new(&config);
+        let distiller = Arc::new(Distiller::with_bedrock_config(
+            &config,
+            &bedrock_config,
+            settings,
+        ));
+        return worker::run(
+            distiller,
+            sqs_client,
+            Options {
+                language_code,
+                language_options,
+                min_language_confidence,
+                fallback_language,
+                media_format,
+                on_empty_transcript,
+                sections,
+                speakers_expected,
+                job_prefix,
+                keep_job,
+                redact_pii,
+                redact_summary,
+                write_output: false,
+                quiet,
+                ..Options::default()
+            },
+        )
+        .await;
+    }
+
+    // Required unless a subcommand is given
+    let mut input_audio_files = input_audio_file;
+    if let Some(input_dir) = input_dir {
+        input_audio_files.extend(audio_files_in(&input_dir)?);
+    }
+    if input_audio_files.is_empty() {
+        bail!("--input-audio-file or --input-dir is required");
+    }
+    let batch = input_audio_files.len() > 1;
+    if input_audio_files.iter().filter(|file| *file == "-").count() > 1 {
+        bail!("stdin (\"-\") can only be read once");
+    }
+    // Several inputs can only share an output file by appending to it
+    if batch && output_file.is_some() && !append {
+        bail!("--output-file with several inputs requires --append; without it, each input is written to a file named after it");
+    }
+
+    let output_s3 = output_s3.as_deref().map(s3::parse_s3_prefix).transpose()?;
+    if output_s3.is_some()
+        && output_types
+            .iter()
+            .all(|output_type| output_type.extension().is_none())
+    {
+        bail!("--output-s3 needs an output type that writes a file, e.g. -o markdown");
+    }
+
+    // Fail before doing any work if the email can't be sent
+    let ses_from = settings.notify.ses_from.clone();
+    if !email_to.is_empty() && ses_from.is_none() {
+        bail!("--email-to requires notify.ses_from to be set in config.toml");
+    }
+
+    let cutoff = since.as_deref().map(parse_since).transpose()?;
+    // Downloaded and piped audio is kept here until the end of the run
+    let mut temp_dirs = Vec::new();
+    let mut inputs = Vec::new();
+    for input_audio_file in input_audio_files {
+        let remote = input_audio_file == "-" || is_url(&input_audio_file);
+        if cutoff.is_some() && (remote || input_audio_file.starts_with("s3://")) {
+            bail!("--since only applies to local files");
+        }
+
+        // An s3:// URI is transcribed in place instead of being uploaded
+        let input = if input_audio_file.starts_with("s3://") {
+            if bucket.is_some() {
+                bail!("--bucket doesn't apply to s3:// input, which is transcribed in place");
+            }
+            let (bucket, key) = s3::parse_s3_uri(&input_audio_file)?;
+            Input::S3 { bucket, key }
+        } else if remote {
+            let temp_dir = TempDir::new()?;
+            let path = if input_audio_file == "-" {
+                read_stdin(&temp_dir)?
+            } else {
+                if !quiet {
+                    println!("🌐 Downloading {}", input_audio_file);
+                }
+                download(&input_audio_file, &temp_dir).await?
+            };
+            temp_dirs.push(temp_dir);
+            Input::File(path)
+        } else {
+            Input::File(PathBuf::from(&input_audio_file))
+        };
+
+        if let Some(cutoff) = cutoff {
+            let input_path = shellexpand::tilde(&input_audio_file).to_string();
+            if !modified_since(Path::new(&input_path), cutoff)? {
+                if !quiet {
+                    println!(
+                        "⏭️  Skipping {}: not modified since {}",
+                        input_audio_file,
+                        since.as_deref().unwrap_or_default()
+                    );
+                }
+                continue;
+            }
+        }
+
+        inputs.push((input_audio_file, input));
+    }
+    if inputs.is_empty() {
+        return Ok(());
+    }
+
+    let s3_client = s3::client(&config, &settings);
+
+    if !quiet {
+        println!("🧙 Welcome to Distill CLI");
+    }
+
+    // Only local files are uploaded
+    let has_files = inputs
+        .iter()
+        .any(|(_, input)| matches!(input, Input::File(_)));
+    let upload_bucket = if has_files {
+        let interactive = !non_interactive && std::io::stdin().is_terminal();
+        let bucket = select_bucket(&s3_client, bucket, s3_bucket_name, interactive).await?;
+        if !quiet {
+            println!("📦 S3 bucket name: {}", bucket);
+        }
+        bucket
+    } else {
+        String::new()
+    };
+
+    let delete_s3_object = delete_s3_object == "Y" || cleanup || settings.s3.cleanup;
+    let ses_client = aws_sdk_sesv2::Client::new(&config);
+    let email = ses_from
+        .filter(|_| !email_to.is_empty() && !dry_run)
+        .map(|ses_from| (ses_from, email_to));
+    let post_hook = post_hook.or_else(|| settings.hooks.post_command.clone());
+    let slack_webhook = slack_webhook
+        .or_else(|| {
+            settings
+                .notify
+                .slack
+                .then(|| settings.slack.webhook_endpoint.clone())
+                .flatten()
+        })
+        .filter(|_| !dry_run);
+    let delivery = Delivery {
+        email: email.as_ref(),
+        output_s3: output_s3.as_ref(),
+        open,
+        clipboard,
+        post_hook: post_hook.as_deref(),
+        slack_webhook: slack_webhook.as_deref(),
+        quiet,
+        non_interactive,
+    };
+    let key_prefix = s3_prefix
+        .or_else(|| settings.aws.s3_key_prefix.clone())
+        .unwrap_or_default();
+    let audit_log = settings
+        .audit
+        .log_file
+        .as_ref()
+        .map(|path| PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref()));
+    let distiller = Distiller::with_bedrock_config(&config, &bedrock_config, settings);
+    let opts = Options {
+        bucket: upload_bucket,
+        key_prefix,
+        output_types,
+        output_file,
         language_code,
+        language_options,
+        min_language_confidence,
+        fallback_language,
+        media_format,
+        on_empty_transcript,
+        sections,
+        compare_models: match compare.as_slice() {
+            [model_a, model_b] => Some((model_a.clone(), model_b.clone())),
+            _ => None,
+        },
+        stream,
+        record_prompt,
+        speakers_expected,
+        job_prefix,
+        keep_job,
+        save_raw_transcript,
+        show_confidence,
+        keep_alternatives,
+        talk_time,
+        redact_pii,
+        redact_summary,
+        append,
         delete_s3_object,
-    } = Opt::parse();
+        summarize: !no_summarize,
+        write_transcript: !no_transcript,
+        write_output: true,
+        dry_run,
+        cache: !no_cache,
+        quiet,
+    };
 
-    let s3_client = Client::new(&config);
+    let count = inputs.len();
+    let mut failed = Vec::new();
+    for (input_audio_file, input) in inputs {
+        let mut opts = opts.clone();
+        if batch {
+            if !quiet {
+                println!("\n🎧 {}", input_audio_file);
+            }
+            // Name each output after its input, e.g. meeting1.md for meeting1.m4a
+            if opts.output_file.is_none() {
+                opts.output_file = opts
+                    .output_types
+                    .iter()
+                    .find_map(|output_type| output_type.extension())
+                    .map(|extension| batch_output_file(&input, extension));
+            }
+        }
+
+        let bucket = match &input {
+            Input::S3 { bucket, .. } => bucket.clone(),
+            Input::File(_) => opts.bucket.clone(),
+        };
+        let started = Instant::now();
+        let (result, interrupted) = tokio::select! {
+            result = process_input(
+                &distiller,
+                &ses_client,
+                input,
+                opts,
+                &delivery,
+            ) => (result, false),
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("\n🛑 Interrupted; cleaning up the uploaded audio and transcription job...");
+                distiller.clean_up_interrupted().await;
+                (Err(anyhow!("Interrupted")), true)
+            }
+        };
+
+        // Every run is recorded, whether it succeeded or not
+        if let Some(audit_log) = &audit_log {
+            let mut record =
+                audit::AuditRecord::new(&input_audio_file, started.elapsed().as_secs_f64());
+            record.bucket = Some(bucket);
+            match &result {
+                Ok(result) => {
+                    record.job_name = result.job_name.clone();
+                    record.model_ids = result.model_ids.clone();
+                    record.language_code = result.language_code.clone();
+                }
+                Err(err) => {
+                    record.success = false;
+                    record.error = Some(format!("{:#}", err));
+                }
+            }
+            // A failed audit write doesn't fail the run it records. The run's
+            // spinner is done by now, so this only prints the warning.
+            if let Err(err) = audit::append(audit_log, &record) {
+                Progress::new("", true).warn(&format!("{:#}", err));
+            }
+        }
+        if interrupted {
+            bail!("Interrupted");
+        }
+
+        match result {
+            Ok(_) => {}
+            Err(err) if batch => {
+                eprintln!("❌ Failed to process {}: {:#}", input_audio_file, err);
+                failed.push(input_audio_file);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    if batch && !quiet {
+        println!();
+        println!("✅ {} of {} files processed", count - failed.len(), count);
+    }
+    if batch && !failed.is_empty() {
+        bail!("Failed to process: {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+// Transcribe and summarize one input, then upload, email, run the post hook
+// on, notify Slack about, open and copy the result as requested
+async fn process_input(
+    distiller: &Distiller,
+    ses_client: &aws_sdk_sesv2::Client,
+    input: Input,
+    opts: Options,
+    delivery: &Delivery<'_>,
+) -> Result<DistillResult> {
+    let result = distiller.run(input, opts).await?;
+    let summary = if result.comparison.is_empty() {
+        result.summary.clone()
+    } else {
+        result
+            .comparison
+            .iter()
+            .map(|summary| format!("## {}\n\n{}", summary.model_id, summary.text))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+
+    // Where the output went, for the Slack message
+    let mut locations: Vec<String> = result
+        .output_files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    if let Some((bucket, prefix)) = delivery.output_s3 {
+        for output_file in &result.output_files {
+            let file_name = output_file
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let key = s3::object_key(prefix, &file_name);
+            distiller.put_file(bucket, &key, output_file).await?;
+            locations.push(format!("s3://{}/{}", bucket, key));
+            if !delivery.quiet {
+                println!("☁️  {} uploaded to s3://{}/{}", file_name, bucket, key);
+            }
+        }
+    }
+
+    let source_name = result.s3_uri.rsplit('/').next().unwrap_or_default();
+    if let Some((ses_from, email_to)) = delivery.email {
+        notify::send_email(
+            ses_client,
+            ses_from,
+            email_to,
+            &format!("Distill summary: {}", source_name),
+            &summary,
+            &result.transcription,
+        )
+        .await?;
+        if !delivery.quiet {
+            println!("📧 Summary emailed to {}", email_to.join(", "));
+        }
+    }
 
+    if let Some(command) = delivery.post_hook {
+        for output_file in &result.output_files {
+            hook::run_post_hook(command, output_file)?;
+            if !delivery.quiet {
+                println!("🪝 Post hook ran on {}", output_file.display());
+            }
+        }
+    }
+
+    // A failed notification doesn't fail a finished run
+    if let Some(webhook_url) = delivery.slack_webhook {
+        match notify::send_slack_message(webhook_url, source_name, &summary, &locations).await {
+            Ok(()) if !delivery.quiet => println!("💬 Slack notified"),
+            Ok(()) => {}
+            Err(err) => eprintln!("⚠️  Unable to notify Slack: {:#}", err),
+        }
+    }
+
+    if delivery.opens_files(std::io::stdout().is_terminal()) {
+        for output_file in &result.output_files {
+            if let Err(err) = opener::open(output_file) {
+                eprintln!("⚠️  Unable to open {}: {}", output_file.display(), err);
+            }
+        }
+    }
+
+    if let Some(content) = delivery.clipboard {
+        let text = match content {
+            ClipboardContent::Summary => summary,
+            ClipboardContent::Transcription => result.transcription.clone(),
+            ClipboardContent::Both if summary.is_empty() => result.transcription.clone(),
+            ClipboardContent::Both => {
+                format!("{}\n\nTranscription:\n\n{}", summary, result.transcription)
+            }
+        };
+        // A missing clipboard (e.g. over SSH) doesn't fail a finished run
+        if text.trim().is_empty() {
+            eprintln!("⚠️  Nothing to copy to the clipboard");
+        } else if let Err(err) = clipboard::copy(&text) {
+            eprintln!("⚠️  Unable to copy to the clipboard: {:#}", err);
+        } else if !delivery.quiet {
+            println!("📋 Copied to the clipboard");
+        }
+    }
+
+    Ok(result)
+}
+
+// The audio files directly inside `dir`, in name order. Files are recognized
+// by their extension.
+fn audio_files_in(dir: &Path) -> Result<Vec<String>> {
+    let dir = PathBuf::from(shellexpand::tilde(&dir.to_string_lossy()).as_ref());
+    let entries = std::fs::read_dir(&dir)
+        .with_context(|| format!("Unable to read directory {}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_audio = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| InputMediaFormat::from_str(extension, true).is_ok());
+        if path.is_file() && is_audio {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+// The output file for one input of a batch: its name with the output type's
+// extension, in the current directory
+fn batch_output_file(input: &Input, extension: &str) -> PathBuf {
+    let name = match input {
+        Input::File(path) => path.to_string_lossy().into_owned(),
+        Input::S3 { key, .. } => key.clone(),
+    };
+    let stem = name
+        .rsplit('/')
+        .next()
+        .map(Path::new)
+        .and_then(Path::file_stem)
+        .unwrap_or_default();
+
+    Path::new(stem).with_extension(extension)
+}
+
+fn is_url(input_audio_file: &str) -> bool {
+    input_audio_file.starts_with("http://") || input_audio_file.starts_with("https://")
+}
+
+// A uniquely named temporary directory, removed with its contents when
+// dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("distill-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path)
+            .with_context(|| format!("Unable to create directory {}", path.display()))?;
+
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Download the audio at `url` into `temp_dir`, keeping the URL's file name so
+// it can be uploaded under a recognizable key
+async fn download(url: &str, temp_dir: &TempDir) -> Result<PathBuf> {
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Unable to download {}", url))?;
+    let file_name = response
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download")
+        .to_string();
+
+    let path = temp_dir.0.join(file_name);
+    let mut file =
+        File::create(&path).with_context(|| format!("Unable to create file {}", path.display()))?;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("Unable to download {}", url))?
+    {
+        file.write_all(&chunk)?;
+    }
+
+    Ok(path)
+}
+
+// Save audio piped to stdin into `temp_dir`. Its media format is detected
+// from its contents.
+fn read_stdin(temp_dir: &TempDir) -> Result<PathBuf> {
+    if std::io::stdin().is_terminal() {
+        bail!("Reading audio from stdin (\"-\"), but nothing is piped in");
+    }
+
+    let path = temp_dir.0.join(format!("stdin-{}", Uuid::new_v4()));
+    let mut file =
+        File::create(&path).with_context(|| format!("Unable to create file {}", path.display()))?;
+    std::io::copy(&mut std::io::stdin().lock(), &mut file)
+        .context("Unable to read audio from stdin")?;
+
+    Ok(path)
+}
+
+// The --bucket or configured bucket if it exists, otherwise one chosen
+// interactively
+async fn select_bucket(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: Option<String>,
+    s3_bucket_name: String,
+    interactive: bool,
+) -> Result<String> {
     let mut bucket_name = String::new();
 
-    println!("🧙 Welcome to Distill CLI");
+    let resp = &list_buckets(s3_client).await;
 
-    let resp = &list_buckets(&s3_client).await;
+    // An explicitly requested bucket must exist; don't fall back to another
+    if let Some(bucket) = bucket {
+        let bucket_names = match resp {
+            Ok(bucket_names) => bucket_names,
+            Err(err) => bail!("\nError getting bucket list: {}", err),
+        };
+        if !bucket_names.contains(&bucket) {
+            bail!(
+                "\nThe S3 bucket '{}' given with --bucket was not found in your account.",
+                bucket
+            );
+        }
+        return Ok(bucket);
+    }
 
     if !s3_bucket_name.is_empty() {
         if resp
@@ -89,7 +1127,6 @@ async fn main() -> Result<()> {
             .and_then(|buckets| buckets.iter().find(|b| b.as_str() == s3_bucket_name))
             .is_some()
         {
-            println!("📦 S3 bucket name: {}", s3_bucket_name);
             bucket_name = s3_bucket_name;
         } else {
             println!(
@@ -99,6 +1136,10 @@ async fn main() -> Result<()> {
         }
     }
 
+    if bucket_name.is_empty() && !interactive {
+        bail!("\nNo valid S3 bucket configured. Set aws.s3_bucket_name in config.toml to an existing bucket; there's no terminal to choose one interactively.");
+    }
+
     if bucket_name.is_empty() {
         match resp {
             Ok(bucket_names) => {
@@ -121,197 +1162,103 @@ async fn main() -> Result<()> {
         bail!("\nNo valid S3 bucket found. Please check your AWS configuration.");
     }
 
-    let mut spinner = Spinner::new(spinners::Dots7, "Uploading file to S3...", Color::Green);
-
-    // Load the bucket region and create a new client to use that region
-    let region = bucket_region(&s3_client, &bucket_name).await?;
-    println!();
-    spinner.update(
-        spinners::Dots7,
-        format!("Using bucket region {}", region),
-        None,
-    );
-    let regional_config = load_config(Some(region)).await;
-    let regional_s3_client = Client::new(&regional_config);
+    Ok(bucket_name)
+}
 
-    // Handle conversion of relative paths to absolute paths
-    let file_path = Path::new(&input_audio_file);
-    let file_name = file_path
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .into_owned();
+// Print the supported language codes and their names as a table
+fn print_languages() {
+    let width = SUPPORTED_LANGUAGES
+        .iter()
+        .map(|(code, _)| code.len())
+        .max()
+        .unwrap_or_default();
 
-    let absolute_path = shellexpand::tilde(file_path.to_str().unwrap()).to_string();
-    let absolute_path = Path::new(&absolute_path);
+    println!("{:width$}  Language", "Code");
+    println!("{:width$}  --------", "----");
+    for (code, name) in SUPPORTED_LANGUAGES {
+        println!("{:width$}  {}", code, name);
+    }
+}
 
-    if !absolute_path.exists() {
-        bail!("\nThe path {} does not exist.", absolute_path.display());
+// Write the default config to `path`, refusing to overwrite an existing file
+// unless `force` is set
+fn init_config(path: &Path, force: bool) -> Result<()> {
+    let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+    if path.exists() && !force {
+        bail!(
+            "{} already exists; pass --force to overwrite it",
+            path.display()
+        );
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Unable to create directory {}", parent.display()))?;
     }
 
-    let canonicalized_path = absolute_path.canonicalize()?;
-    let body = ByteStream::from_path(&canonicalized_path)
-        .await
-        .with_context(|| format!("Error loading file: {}", canonicalized_path.display()))?;
-
-    let _upload_result = regional_s3_client
-        .put_object()
-        .bucket(&bucket_name)
-        .key(&file_name)
-        .body(body)
-        .send()
-        .await
-        .context("Failed to upload to S3")?;
+    std::fs::write(&path, DEFAULT_CONFIG)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    println!("📄 Wrote {}", path.display());
+    println!(
+        "   Set s3_bucket_name under [aws], then run `distill-cli doctor` to check your setup."
+    );
 
-    let s3_uri = format!("s3://{}/{}", bucket_name, file_name);
+    Ok(())
+}
 
+// Run the setup checks and print a pass/fail report with remediation hints
+async fn run_doctor(
+    config: &SdkConfig,
+    bedrock_config: &SdkConfig,
+    settings: &Settings,
+) -> Result<()> {
+    println!("🩺 Checking your Distill setup...");
     println!();
-    spinner.update(spinners::Dots7, "Summarizing text...", None);
-
-    // Transcribe the audio
-    let transcription: String = transcribe::transcribe_audio(
-        &regional_config,
-        file_path,
-        &s3_uri,
-        &mut spinner,
-        &language_code,
-    )
-    .await?;
-
-    // Summarize the transcription
-    spinner.update(spinners::Dots7, "Summarizing text...", None);
-    let summarized_text = summarize::summarize_text(&config, &transcription, &mut spinner).await?;
-
-    match output_type {
-        OutputType::Word => {
-            let output_file_path_word = Path::new("summary.docx");
-            let file = File::create(output_file_path_word)
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-
-            // Creating a new document and adding paragraphs
-            let doc = Docx::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&summarized_text)))
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("\n\n")))
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("Transcription:\n")))
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&transcription)));
-
-            // Building and saving the document
-            doc.build()
-                .pack(file)
-                .map_err(|e| anyhow::anyhow!("Error writing Word document: {}", e))?;
-
-            spinner.success("Done!");
-            println!(
-                "💾 Summary and transcription written to {}",
-                output_file_path_word.display()
-            );
-        }
-        OutputType::Text => {
-            let output_file_path_txt = Path::new("summary.txt");
-            let mut file = File::create(output_file_path_txt)
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-
-            file.write_all(summarized_text.as_bytes())
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-            file.write_all(b"\n\nTranscription:\n")
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-            file.write_all(transcription.as_bytes())
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-
-            spinner.success("Done!");
-            println!(
-                "💾 Summary and transcription written to {}",
-                output_file_path_txt.display()
-            );
-        }
-        OutputType::Terminal => {
-            spinner.success("Done!");
-            println!();
-            println!("Summary:\n{}\n", summarized_text);
-            println!("Transcription:\n{}\n", transcription);
-        }
-        OutputType::Markdown => {
-            let output_file_path_md = Path::new("summary.md");
-            let mut file = File::create(output_file_path_md)
-                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
-
-            let summary_md = format!("# Summary\n\n{}", summarized_text);
-            let mut transcription_md = format!("\n\n# Transcription\n\n{}", transcription);
-            transcription_md = transcription_md.replace("spk_", "\nspk_");
-            let markdown_content = format!("{}{}", summary_md, transcription_md);
 
-            file.write_all(markdown_content.as_bytes())
-                .map_err(|e| anyhow::anyhow!("Error writing Markdown file: {}", e))?;
-
-            spinner.success("Done!");
-            println!(
-                "💾 Summary and transcription written to {}",
-                output_file_path_md.display()
-            );
-        }
-        OutputType::Slack => {
-            let client = ReqwestClient::new();
-
-            let slack_webhook_endpoint = settings
-                .get_string("slack.webhook_endpoint")
-                .unwrap_or_default();
-
-            if slack_webhook_endpoint.is_empty() {
-                spinner.stop_and_persist(
-                    "⚠️",
-                    "Slack webhook endpoint is not configured. Skipping Slack notification.",
-                );
-                println!("Summary:\n{}\n", summarized_text);
-            } else {
-                let content = format!("A summarization job just completed:\n\n{}", summarized_text);
-                let payload = json!({
-                    "content": content
-                });
-                match client
-                    .post(slack_webhook_endpoint)
-                    .header("Content-Type", "application/json")
-                    .json(&payload)
-                    .send()
-                    .await
-                {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            spinner.success("Summary sent to Slack!");
-                        } else {
-                            spinner.stop_and_persist("❌", "Failed to send summary to Slack!");
-                            eprintln!("Error sending summary to Slack: {}", response.status());
-                        }
-                    }
-                    Err(err) => {
-                        spinner.stop_and_persist("❌", "Failed to send summary to Slack!");
-                        eprintln!("Error sending summary to Slack: {}", err);
-                    }
-                };
+    let checks = doctor::run_checks(config, bedrock_config, settings).await;
+    for check in &checks {
+        match &check.result {
+            Ok(detail) => println!("✅ {}: {}", check.name, detail),
+            Err(err) => {
+                println!("❌ {}: {}", check.name, err);
+                println!("   Hint: {}", check.hint);
             }
         }
     }
 
-    // After processing, check if the user wants to delete the S3 object
-    if delete_s3_object == "Y" {
-        s3_client
-            .delete_object()
-            .bucket(&bucket_name)
-            .key(&file_name)
-            .send()
-            .await?;
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+    println!();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
     }
+    println!("All {} checks passed!", checks.len());
 
     Ok(())
 }
 
-// Load the user's aws config, default region to us-east-1 if none is provided or can be found
-async fn load_config(region: Option<Region>) -> SdkConfig {
+// The shared AWS config. Its region is, in order: AWS_REGION, then
+// AWS_DEFAULT_REGION, then the (named) profile in ~/.aws/config, then EC2
+// instance metadata, and only if none of these is set, us-east-1. S3 and
+// Transcribe calls for an upload are made in its bucket's region instead, and
+// Bedrock calls in bedrock.region (--region) when set.
+async fn load_config(endpoint_url: Option<String>, profile: Option<&str>) -> SdkConfig {
     let mut config = aws_config::from_env();
-    match region {
-        Some(region) => config = config.region(region),
-        None => {
-            config = config.region(RegionProviderChain::default_provider().or_else("us-east-1"))
-        }
+    let region_chain = match profile {
+        Some(profile) => DefaultRegionChain::builder().profile_name(profile).build(),
+        None => DefaultRegionChain::builder().build(),
+    };
+    config = config.region(RegionProviderChain::first_try(region_chain).or_else("us-east-1"));
+
+    // Credentials come from the named profile in ~/.aws/config and
+    // ~/.aws/credentials instead of the default chain
+    if let Some(profile) = profile {
+        config = config.profile_name(profile);
+    }
+
+    if let Some(endpoint_url) = endpoint_url {
+        config = config.endpoint_url(endpoint_url);
     }
 
     // Resolves issues with uploading large S3 files
@@ -324,32 +1271,129 @@ async fn load_config(region: Option<Region>) -> SdkConfig {
     config.load().await
 }
 
-async fn list_buckets(client: &Client) -> Result<Vec<String>> {
-    let resp = client.list_buckets().send().await?;
-    let buckets = resp.buckets();
+// A --tag KEY=VALUE; the value may be empty
+fn parse_tag(tag: &str) -> Result<(String, String)> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => bail!("Invalid tag '{}'; expected KEY=VALUE", tag),
+    }
+}
+
+// Resolve a --since value to a cutoff time. Accepts a duration relative to
+// now (e.g. "24h", "7days") or an absolute date/timestamp (e.g. "2024-06-01",
+// "2024-06-01T09:00:00Z").
+fn parse_since(since: &str) -> Result<SystemTime> {
+    if let Ok(duration) = humantime::parse_duration(since) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .context("The --since duration is too large");
+    }
 
-    let bucket_names: Vec<String> = buckets
-        .iter()
-        .map(|bucket| bucket.name().unwrap_or_default().to_string())
-        .collect();
+    let timestamp = if since.len() == 10 {
+        format!("{}T00:00:00Z", since)
+    } else {
+        since.to_string()
+    };
 
-    Ok(bucket_names)
+    humantime::parse_rfc3339_weak(&timestamp).with_context(|| {
+        format!(
+            "Invalid --since value '{}'. Use a duration like 24h or a date like 2024-06-01",
+            since
+        )
+    })
 }
 
-async fn bucket_region(client: &Client, bucket_name: &str) -> Result<Region> {
-    let resp = client
-        .get_bucket_location()
-        .bucket(bucket_name)
-        .send()
-        .await?;
+// Returns true if the file at `path` was last modified at or after `cutoff`.
+fn modified_since(path: &Path, cutoff: SystemTime) -> Result<bool> {
+    let modified = path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Unable to read modification time of {}", path.display()))?;
 
-    let location_constraint = resp
-        .location_constraint()
-        .context("Bucket has no location_constraint")?;
+    Ok(modified >= cutoff)
+}
 
-    if location_constraint.as_str() == "" {
-        Ok(Region::new("us-east-1"))
-    } else {
-        Ok(Region::new(location_constraint.as_str().to_owned()))
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    // 2024-06-01T00:00:00Z
+    const JUNE_1: u64 = 1_717_200_000;
+
+    #[test]
+    fn parse_since_accepts_a_duration() {
+        let cutoff = parse_since("24h").unwrap();
+        let age = SystemTime::now().duration_since(cutoff).unwrap();
+        assert!(age >= Duration::from_secs(24 * 3600));
+        assert!(age < Duration::from_secs(24 * 3600 + 60));
+    }
+
+    #[test]
+    fn parse_since_accepts_a_date_or_timestamp() {
+        assert_eq!(
+            parse_since("2024-06-01").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(JUNE_1)
+        );
+        assert_eq!(
+            parse_since("2024-06-01T09:00:00Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(JUNE_1 + 9 * 3600)
+        );
+    }
+
+    #[test]
+    fn parse_since_rejects_other_values() {
+        for since in ["", "yesterday", "2024-13-01", "24 parsecs"] {
+            let err = parse_since(since).unwrap_err();
+            assert!(
+                err.to_string().starts_with("Invalid --since value"),
+                "{}",
+                since
+            );
+        }
+    }
+
+    #[test]
+    fn modified_since_compares_the_mtime_with_the_cutoff() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.0.join("meeting.mp3");
+        let file = File::create(&path).unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(JUNE_1);
+        file.set_modified(modified).unwrap();
+
+        let second = Duration::from_secs(1);
+        assert!(modified_since(&path, modified - second).unwrap());
+        assert!(modified_since(&path, modified).unwrap());
+        assert!(!modified_since(&path, modified + second).unwrap());
+    }
+
+    #[test]
+    fn modified_since_fails_for_a_missing_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let err = modified_since(&temp_dir.0.join("missing.mp3"), SystemTime::now()).unwrap_err();
+        assert!(err.to_string().contains("missing.mp3"));
+    }
+
+    fn delivery(open: bool, quiet: bool, non_interactive: bool) -> Delivery<'static> {
+        Delivery {
+            email: None,
+            output_s3: None,
+            open,
+            clipboard: None,
+            post_hook: None,
+            slack_webhook: None,
+            quiet,
+            non_interactive,
+        }
+    }
+
+    #[test]
+    fn open_only_opens_files_for_an_interactive_terminal() {
+        assert!(delivery(true, false, false).opens_files(true));
+        assert!(!delivery(false, false, false).opens_files(true));
+        assert!(!delivery(true, false, false).opens_files(false));
+        assert!(!delivery(true, true, false).opens_files(true));
+        assert!(!delivery(true, false, true).opens_files(true));
     }
 }
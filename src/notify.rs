@@ -0,0 +1,162 @@
+use anyhow::{anyhow, bail, Result};
+use aws_sdk_sesv2::primitives::Blob;
+use aws_sdk_sesv2::types::{Destination, EmailContent, RawMessage};
+use aws_sdk_sesv2::Client;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::Client as ReqwestClient;
+use serde_json::json;
+use uuid::Uuid;
+
+// Slack cuts off long messages, so summaries are shortened to this many
+// characters
+const SLACK_SUMMARY_CHARS: usize = 2500;
+
+// Email the summary to `to` with Amazon SES, attaching the transcription as
+// transcript.txt. Throttled requests are retried by the SDK like any other
+// AWS call.
+pub async fn send_email(
+    client: &Client,
+    from: &str,
+    to: &[String],
+    subject: &str,
+    summary: &str,
+    transcription: &str,
+) -> Result<()> {
+    if to.is_empty() {
+        bail!("No email recipients given");
+    }
+
+    let message = email_message(from, to, subject, summary, transcription);
+    let raw_message = RawMessage::builder()
+        .data(Blob::new(message))
+        .build()
+        .map_err(|err| anyhow!(err))?;
+
+    let result = client
+        .send_email()
+        .from_email_address(from)
+        .destination(
+            Destination::builder()
+                .set_to_addresses(Some(to.to_vec()))
+                .build(),
+        )
+        .content(EmailContent::builder().raw(raw_message).build())
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => match err.as_service_error() {
+            // SES rejects mail from identities that haven't been verified
+            Some(service_err)
+                if service_err.is_message_rejected()
+                    || service_err.is_mail_from_domain_not_verified_exception() =>
+            {
+                Err(anyhow!(err).context(format!(
+                    "Amazon SES rejected the email. Check that {} is a verified identity in the SES console for this region, and that recipients are verified if your account is in the SES sandbox.",
+                    from
+                )))
+            }
+            _ => Err(anyhow!(err).context("Failed to send email with Amazon SES")),
+        },
+    }
+}
+
+// A MIME message with the summary as the body and the transcription as an
+// attachment. Parts are base64-encoded, as transcript lines can be longer
+// than SMTP allows.
+pub fn email_message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    summary: &str,
+    transcription: &str,
+) -> String {
+    let boundary = format!("distill-{}", Uuid::new_v4());
+
+    format!(
+        "From: {from}\r\n\
+         To: {to}\r\n\
+         Subject: =?UTF-8?B?{subject}?=\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=UTF-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {body}\r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=UTF-8; name=\"transcript.txt\"\r\n\
+         Content-Disposition: attachment; filename=\"transcript.txt\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {attachment}\r\n\
+         --{boundary}--\r\n",
+        to = to.join(", "),
+        subject = STANDARD.encode(subject),
+        body = base64_lines(summary),
+        attachment = base64_lines(transcription),
+    )
+}
+
+// Base64 with lines of at most 76 characters, as MIME requires
+fn base64_lines(text: &str) -> String {
+    STANDARD
+        .encode(text)
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+// Post a message saying `source_name` is done to a Slack incoming webhook,
+// with the (shortened) summary and where the output went
+pub async fn send_slack_message(
+    webhook_url: &str,
+    source_name: &str,
+    summary: &str,
+    locations: &[String],
+) -> Result<()> {
+    post_to_slack(webhook_url, &slack_message(source_name, summary, locations)).await
+}
+
+// Post `text` to a Slack incoming webhook, for both Slack output and run
+// notifications
+pub async fn post_to_slack(webhook_url: &str, text: &str) -> Result<()> {
+    let response = ReqwestClient::new()
+        .post(webhook_url)
+        .json(&json!({ "text": text }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!("Slack responded with {}", response.status());
+    }
+
+    Ok(())
+}
+
+fn slack_message(source_name: &str, summary: &str, locations: &[String]) -> String {
+    let mut message = format!("Distill finished {}", source_name);
+
+    let summary = summary.trim();
+    if !summary.is_empty() {
+        message.push_str("\n\n");
+        match summary.char_indices().nth(SLACK_SUMMARY_CHARS) {
+            Some((end, _)) => {
+                message.push_str(summary[..end].trim_end());
+                message.push('…');
+            }
+            None => message.push_str(summary),
+        }
+    }
+
+    if !locations.is_empty() {
+        message.push_str("\n\nOutput: ");
+        message.push_str(&locations.join(", "));
+    }
+
+    message
+}
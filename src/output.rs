@@ -0,0 +1,1202 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use docx_rs::{read_docx, Docx, Paragraph, Run, Style, StyleType, Table, TableCell, TableRow};
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference,
+};
+use serde_json::json;
+
+use crate::notify;
+use crate::progress::Progress;
+use crate::settings::Settings;
+use crate::summarize::{section_title, ModelSummary, PromptRecord};
+use crate::transcript::{caption_segments, SpeakerTurn, TranscriptSegment};
+use crate::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputType {
+    Terminal,
+    Text,
+    Word,
+    Markdown,
+    Slack,
+    Vtt,
+    Srt,
+    Pdf,
+    Json,
+}
+
+impl OutputType {
+    // The extension of the file written for this output type, if any
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            OutputType::Terminal | OutputType::Slack => None,
+            OutputType::Text => Some("txt"),
+            OutputType::Word => Some("docx"),
+            OutputType::Markdown => Some("md"),
+            OutputType::Vtt => Some("vtt"),
+            OutputType::Srt => Some("srt"),
+            OutputType::Pdf => Some("pdf"),
+            OutputType::Json => Some("json"),
+        }
+    }
+}
+
+// The summary and transcription of one input, as written to each output
+#[derive(Debug, Clone, Copy)]
+pub struct Document<'a> {
+    // Identifies the input, e.g. "meeting.mp3", when appending to a file
+    pub source_name: &'a str,
+    pub summary: &'a str,
+    // The transcription as written, with any --show-confidence markers, or
+    // empty with --no-transcript
+    pub transcription: &'a str,
+    // The speaker turns, marked like the transcription. Subtitles are written
+    // from them even with --no-transcript.
+    pub turns: &'a [SpeakerTurn],
+    // With --keep-alternatives, the alternatives of each segment
+    pub alternatives: &'a [TranscriptSegment],
+    pub job_name: &'a str,
+    pub language_code: &'a str,
+    // The model that wrote the summary, if it was summarized
+    pub model_id: Option<&'a str>,
+}
+
+// Write the summary and transcription to an output. Returns the path of the
+// written file for file-based outputs.
+pub async fn write_output(
+    output_type: OutputType,
+    document: &Document<'_>,
+    opts: &Options,
+    settings: &Settings,
+    spinner: &mut Progress,
+) -> Result<Option<PathBuf>> {
+    let Document {
+        source_name,
+        summary: summarized_text,
+        transcription,
+        turns,
+        ..
+    } = *document;
+    let output_file = output_file_for(opts, output_type);
+    let transcript_turns = written_turns(transcription, turns);
+
+    match output_type {
+        OutputType::Word => {
+            let output_file_path_word = output_path(output_file.as_deref(), "summary.docx")?;
+            let section = append_section(opts, output_file_path_word, source_name);
+            write_word(
+                output_file_path_word,
+                summarized_text,
+                transcript_turns,
+                section.as_deref(),
+            )?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 {} written to {}",
+                written_contents(summarized_text, transcription),
+                output_file_path_word.display()
+            ));
+            Ok(Some(output_file_path_word.to_path_buf()))
+        }
+        OutputType::Text => {
+            let output_file_path_txt = output_path(output_file.as_deref(), "summary.txt")?;
+            let section = append_section(opts, output_file_path_txt, source_name);
+            write_text(
+                output_file_path_txt,
+                summarized_text,
+                transcription,
+                section.as_deref(),
+            )?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 {} written to {}",
+                written_contents(summarized_text, transcription),
+                output_file_path_txt.display()
+            ));
+            Ok(Some(output_file_path_txt.to_path_buf()))
+        }
+        OutputType::Terminal => {
+            spinner.success("Done!");
+            spinner.note("");
+            // A streamed summary has already been printed
+            if !opts.stream && !summarized_text.is_empty() {
+                println!("Summary:\n{}\n", summarized_text);
+            }
+            if !transcription.is_empty() {
+                println!("Transcription:\n{}\n", transcription);
+            }
+            Ok(None)
+        }
+        OutputType::Markdown => {
+            let output_file_path_md = output_path(output_file.as_deref(), "summary.md")?;
+            let section = append_section(opts, output_file_path_md, source_name);
+            write_markdown(
+                output_file_path_md,
+                summarized_text,
+                transcript_turns,
+                section.as_deref(),
+            )?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 {} written to {}",
+                written_contents(summarized_text, transcription),
+                output_file_path_md.display()
+            ));
+            Ok(Some(output_file_path_md.to_path_buf()))
+        }
+        OutputType::Slack => {
+            send_to_slack(summarized_text, settings, spinner).await;
+            Ok(None)
+        }
+        OutputType::Srt => {
+            let output_file_path_srt = output_path(output_file.as_deref(), "summary.srt")?;
+            std::fs::write(output_file_path_srt, srt(turns))
+                .map_err(|e| anyhow::anyhow!("Error writing SRT file: {}", e))?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 Transcription written to {}",
+                output_file_path_srt.display()
+            ));
+            Ok(Some(output_file_path_srt.to_path_buf()))
+        }
+        OutputType::Pdf => {
+            let output_file_path_pdf = output_path(output_file.as_deref(), "summary.pdf")?;
+            write_pdf(output_file_path_pdf, summarized_text, transcript_turns)?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 {} written to {}",
+                written_contents(summarized_text, transcription),
+                output_file_path_pdf.display()
+            ));
+            Ok(Some(output_file_path_pdf.to_path_buf()))
+        }
+        OutputType::Json => {
+            let output_file_path_json = output_path(output_file.as_deref(), "summary.json")?;
+            let content = serde_json::to_string_pretty(&json_document(document))?;
+            std::fs::write(output_file_path_json, content + "\n")
+                .map_err(|e| anyhow::anyhow!("Error writing JSON file: {}", e))?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 {} written to {}",
+                written_contents(summarized_text, transcription),
+                output_file_path_json.display()
+            ));
+            Ok(Some(output_file_path_json.to_path_buf()))
+        }
+        OutputType::Vtt => {
+            let output_file_path_vtt = output_path(output_file.as_deref(), "summary.vtt")?;
+            std::fs::write(output_file_path_vtt, webvtt(turns))
+                .map_err(|e| anyhow::anyhow!("Error writing WebVTT file: {}", e))?;
+
+            spinner.success("Done!");
+            spinner.note(&format!(
+                "💾 Transcription written to {}",
+                output_file_path_vtt.display()
+            ));
+            Ok(Some(output_file_path_vtt.to_path_buf()))
+        }
+    }
+}
+
+// The whole run as a single JSON document, for scripts and jq. Speaker turns
+// are left out along with the transcription (--no-transcript), and segment
+// alternatives are only included with --keep-alternatives.
+fn json_document(document: &Document) -> serde_json::Value {
+    let speakers: Vec<serde_json::Value> = document
+        .turns
+        .iter()
+        .filter(|_| !document.transcription.is_empty())
+        .map(|turn| {
+            json!({
+                "speaker": turn.speaker,
+                "text": turn.text,
+                "start": turn.start,
+                "end": turn.end,
+            })
+        })
+        .collect();
+
+    let mut json = json!({
+        "source": document.source_name,
+        "job_name": document.job_name,
+        "language": document.language_code,
+        "model_id": document.model_id,
+        "summary": document.summary,
+        "transcription": document.transcription,
+        "speakers": speakers,
+    });
+    if !document.alternatives.is_empty() && !document.transcription.is_empty() {
+        json["alternatives"] = json!(document.alternatives);
+    }
+
+    json
+}
+
+// The turns to write as the transcription: none with --no-transcript
+fn written_turns<'a>(transcription: &str, turns: &'a [SpeakerTurn]) -> &'a [SpeakerTurn] {
+    match transcription.is_empty() {
+        true => &[],
+        false => turns,
+    }
+}
+
+// What a file-based output contains, for the message once it is written. An
+// empty summary (--no-summarize) or transcription (--no-transcript) is left
+// out of the output.
+fn written_contents(summarized_text: &str, transcription: &str) -> &'static str {
+    match (summarized_text.is_empty(), transcription.is_empty()) {
+        (true, _) => "Transcription",
+        (false, true) => "Summary",
+        (false, false) => "Summary and transcription",
+    }
+}
+
+// Render speaker turns as WebVTT cues, with the speaker as a voice span, e.g.
+// "<v spk_0>Hello</v>". Turns without timings can't be cued and are skipped.
+pub fn webvtt(turns: &[SpeakerTurn]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+
+    let timed_turns = turns
+        .iter()
+        .filter_map(|turn| match (turn.start, turn.end) {
+            (Some(start), Some(end)) => Some((turn, start, end)),
+            _ => None,
+        });
+    for (cue, (turn, start, end)) in timed_turns.enumerate() {
+        let text = turn
+            .text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        let payload = if turn.speaker.is_empty() {
+            text
+        } else {
+            format!("<v {}>{}</v>", turn.speaker, text)
+        };
+
+        vtt.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            cue + 1,
+            caption_timestamp(start, '.'),
+            caption_timestamp(end, '.'),
+            payload
+        ));
+    }
+
+    vtt
+}
+
+// Longest span of an SRT caption, in seconds
+const SRT_MAX_CAPTION_SECONDS: f64 = 7.0;
+
+// Render speaker turns as numbered SRT captions of at most
+// SRT_MAX_CAPTION_SECONDS, prefixed with the speaker, e.g. "spk_0: Hello".
+// Captions without timings are skipped.
+pub fn srt(turns: &[SpeakerTurn]) -> String {
+    let segments = caption_segments(turns, SRT_MAX_CAPTION_SECONDS);
+    let timed_segments = segments
+        .iter()
+        .filter_map(|segment| match (segment.start, segment.end) {
+            (Some(start), Some(end)) => Some((segment, start, end)),
+            _ => None,
+        });
+
+    let mut srt = String::new();
+    for (caption, (segment, start, end)) in timed_segments.enumerate() {
+        let text = if segment.speaker.is_empty() {
+            segment.text.clone()
+        } else {
+            format!("{}: {}", segment.speaker, segment.text)
+        };
+
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            caption + 1,
+            caption_timestamp(start, ','),
+            caption_timestamp(end, ','),
+            text
+        ));
+    }
+
+    srt
+}
+
+// Caption timings are HH:MM:SS.mmm in WebVTT and HH:MM:SS,mmm in SRT
+fn caption_timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+// The --output-file path for `output_type`. With several output types, each
+// file-based output gets the path with its own extension, e.g. notes.md and
+// notes.docx.
+pub fn output_file_for(opts: &Options, output_type: OutputType) -> Option<PathBuf> {
+    let output_file = opts.output_file.as_ref()?;
+
+    match output_type.extension() {
+        Some(extension) if opts.output_types.len() > 1 => {
+            Some(output_file.with_extension(extension))
+        }
+        _ => Some(output_file.clone()),
+    }
+}
+
+// Fail early if --output-file can't be written to
+pub fn check_output_file(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        anyhow::bail!(
+            "The output file {} is a directory; pass a file path instead",
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+// The path to write to: `output_file` if given, otherwise `default_name` in the
+// current directory. Missing parent directories are created.
+fn output_path<'a>(output_file: Option<&'a Path>, default_name: &'a str) -> Result<&'a Path> {
+    let Some(path) = output_file else {
+        return Ok(Path::new(default_name));
+    };
+
+    check_output_file(path)?;
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Unable to create directory {}", parent.display()))?;
+    }
+
+    Ok(path)
+}
+
+// When appending to an existing file, the title of the new dated section,
+// e.g. "2024-06-01 — meeting.mp3". None means the file is written from scratch.
+fn append_section(opts: &Options, path: &Path, source_name: &str) -> Option<String> {
+    if opts.append && path.exists() {
+        // RFC 3339 timestamps start with the YYYY-MM-DD date (UTC)
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        Some(format!("{} — {}", &now[..10], source_name))
+    } else {
+        None
+    }
+}
+
+fn write_word(
+    path: &Path,
+    summarized_text: &str,
+    turns: &[SpeakerTurn],
+    section: Option<&str>,
+) -> Result<()> {
+    let doc = match section {
+        Some(title) => {
+            let existing = std::fs::read(path)
+                .map_err(|e| anyhow::anyhow!("Error reading Word document: {}", e))?;
+            let doc = read_docx(&existing)
+                .map_err(|e| anyhow::anyhow!("Error reading Word document: {}", e))?;
+
+            add_word_content(
+                doc.add_paragraph(word_heading(title, 1)),
+                summarized_text,
+                turns,
+                2,
+            )
+        }
+        None => add_word_content(word_styles(Docx::new()), summarized_text, turns, 1),
+    };
+
+    let file = File::create(path).map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+
+    // Building and saving the document
+    doc.build()
+        .pack(file)
+        .map_err(|e| anyhow::anyhow!("Error writing Word document: {}", e))?;
+
+    Ok(())
+}
+
+fn word_styles(doc: Docx) -> Docx {
+    doc.add_style(
+        Style::new("Heading1", StyleType::Paragraph)
+            .name("Heading 1")
+            .bold()
+            .size(32),
+    )
+    .add_style(
+        Style::new("Heading2", StyleType::Paragraph)
+            .name("Heading 2")
+            .bold()
+            .size(26),
+    )
+    .add_style(
+        Style::new("Heading3", StyleType::Paragraph)
+            .name("Heading 3")
+            .bold()
+            .size(24),
+    )
+}
+
+// Add the summary, if any, followed by the transcription, with one paragraph
+// per speaker turn and the speaker label in bold. Summary sections become
+// headings one level below `heading_level`.
+fn add_word_content(
+    mut doc: Docx,
+    summarized_text: &str,
+    turns: &[SpeakerTurn],
+    heading_level: usize,
+) -> Docx {
+    if !summarized_text.is_empty() {
+        doc = doc.add_paragraph(word_heading("Summary", heading_level));
+    }
+
+    for line in summarized_text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+    {
+        doc = match section_title(line) {
+            Some(title) => doc.add_paragraph(word_heading(title, heading_level + 1)),
+            None => doc.add_paragraph(Paragraph::new().add_run(Run::new().add_text(line))),
+        };
+    }
+
+    add_word_transcription(doc, turns, heading_level)
+}
+
+fn add_word_transcription(mut doc: Docx, turns: &[SpeakerTurn], heading_level: usize) -> Docx {
+    if turns.is_empty() {
+        return doc;
+    }
+
+    doc = doc.add_paragraph(word_heading("Transcription", heading_level));
+
+    for turn in turns {
+        let mut paragraph = Paragraph::new();
+        if !turn.speaker.is_empty() {
+            paragraph =
+                paragraph.add_run(Run::new().add_text(format!("{}: ", turn.speaker)).bold());
+        }
+        doc = doc.add_paragraph(paragraph.add_run(Run::new().add_text(&turn.text)));
+    }
+
+    doc
+}
+
+fn word_heading(text: &str, level: usize) -> Paragraph {
+    Paragraph::new()
+        .add_run(Run::new().add_text(text))
+        .style(&format!("Heading{}", level))
+}
+
+fn write_text(
+    path: &Path,
+    summarized_text: &str,
+    transcription: &str,
+    section: Option<&str>,
+) -> Result<()> {
+    let mut file = match section {
+        Some(_) => OpenOptions::new().append(true).open(path),
+        None => File::create(path),
+    }
+    .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+
+    if let Some(title) = section {
+        let underline = "-".repeat(title.chars().count());
+        file.write_all(format!("\n\n{}\n{}\n\n", title, underline).as_bytes())
+            .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    }
+
+    file.write_all(summarized_text.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    if !summarized_text.is_empty() && !transcription.is_empty() {
+        file.write_all(b"\n\n")
+            .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    }
+    if !transcription.is_empty() {
+        file.write_all(b"Transcription:\n")
+            .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+        file.write_all(transcription.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    }
+
+    Ok(())
+}
+
+fn write_markdown(
+    path: &Path,
+    summarized_text: &str,
+    turns: &[SpeakerTurn],
+    section: Option<&str>,
+) -> Result<()> {
+    let (file, markdown_content) = match section {
+        Some(title) => (
+            OpenOptions::new().append(true).open(path),
+            format!(
+                "\n\n## {}\n\n{}",
+                title,
+                markdown_content(summarized_text, turns, "###")
+            ),
+        ),
+        None => (
+            File::create(path),
+            markdown_document(summarized_text, turns),
+        ),
+    };
+    let mut file = file.map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+
+    file.write_all(markdown_content.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Error writing Markdown file: {}", e))?;
+
+    Ok(())
+}
+
+// The Markdown document written by the markdown output type
+pub fn markdown_document(summarized_text: &str, turns: &[SpeakerTurn]) -> String {
+    markdown_content(summarized_text, turns, "##")
+}
+
+fn markdown_content(summarized_text: &str, turns: &[SpeakerTurn], heading: &str) -> String {
+    // Nest summary sections one level below the "Summary" heading
+    let summary_body: Vec<String> = summarized_text
+        .lines()
+        .map(|line| match section_title(line) {
+            Some(title) => format!("{}# {}", heading, title),
+            None => line.to_string(),
+        })
+        .collect();
+    let summary_md = format!("{} Summary\n\n{}", heading, summary_body.join("\n"));
+    let transcription_md = markdown_transcription(turns, heading);
+    if summarized_text.is_empty() {
+        transcription_md.trim_start().to_string()
+    } else {
+        format!("{}{}", summary_md, transcription_md)
+    }
+}
+
+// A table of the speakers, then one paragraph per speaker turn, with the
+// speaker label in bold. Empty when the transcription is left out.
+fn markdown_transcription(turns: &[SpeakerTurn], heading: &str) -> String {
+    if turns.is_empty() {
+        return "\n".to_string();
+    }
+
+    let table = markdown_speaker_table(turns);
+    let paragraphs: Vec<String> = turns
+        .iter()
+        .map(|turn| match turn.speaker.as_str() {
+            "" => turn.text.clone(),
+            speaker => format!("**{}:** {}", speaker, turn.text),
+        })
+        .collect();
+
+    format!(
+        "\n\n{} Transcription\n\n{}{}\n",
+        heading,
+        table,
+        paragraphs.join("\n\n")
+    )
+}
+
+// Each speaker with their number of turns and words, in order of first
+// appearance. Empty when there are no speaker labels.
+fn markdown_speaker_table(turns: &[SpeakerTurn]) -> String {
+    let mut speakers: Vec<(&str, usize, usize)> = Vec::new();
+    for turn in turns.iter().filter(|turn| !turn.speaker.is_empty()) {
+        let words = turn.text.split_whitespace().count();
+        match speakers
+            .iter_mut()
+            .find(|(speaker, _, _)| *speaker == turn.speaker)
+        {
+            Some((_, turn_count, word_count)) => {
+                *turn_count += 1;
+                *word_count += words;
+            }
+            None => speakers.push((&turn.speaker, 1, words)),
+        }
+    }
+    if speakers.is_empty() {
+        return String::new();
+    }
+
+    let rows: Vec<String> = speakers
+        .iter()
+        .map(|(speaker, turns, words)| format!("| {} | {} | {} |", speaker, turns, words))
+        .collect();
+    format!(
+        "| Speaker | Turns | Words |\n| --- | ---: | ---: |\n{}\n\n",
+        rows.join("\n")
+    )
+}
+
+// A4, in millimetres
+const PDF_PAGE_WIDTH: f32 = 210.0;
+const PDF_PAGE_HEIGHT: f32 = 297.0;
+const PDF_MARGIN: f32 = 20.0;
+const PDF_HEADING_SIZE: f32 = 16.0;
+const PDF_SUBHEADING_SIZE: f32 = 13.0;
+const PDF_BODY_SIZE: f32 = 11.0;
+// Characters per body line. The built-in fonts aren't measured, so this is
+// based on Helvetica's average character width at PDF_BODY_SIZE.
+const PDF_LINE_CHARS: usize = 85;
+
+// Write the summary, a page break, then the speaker-labeled transcription.
+// Either may be empty, in which case it is left out along with the break.
+// Text is wrapped and flows onto as many pages as it needs.
+fn write_pdf(path: &Path, summarized_text: &str, turns: &[SpeakerTurn]) -> Result<()> {
+    check_pdf_text(summarized_text, "summary")?;
+    for turn in turns {
+        check_pdf_text(&turn.speaker, "transcription")?;
+        check_pdf_text(&turn.text, "transcription")?;
+    }
+
+    let (doc, page, layer) = PdfDocument::new(
+        "Summary",
+        Mm(PDF_PAGE_WIDTH),
+        Mm(PDF_PAGE_HEIGHT),
+        "Layer 1",
+    );
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| anyhow::anyhow!("Error loading PDF font: {}", e))?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| anyhow::anyhow!("Error loading PDF font: {}", e))?;
+
+    let mut pages = PdfPages {
+        doc: &doc,
+        layer: doc.get_page(page).get_layer(layer),
+        y: PDF_PAGE_HEIGHT - PDF_MARGIN,
+    };
+
+    if !summarized_text.is_empty() {
+        pages.text("Summary", PDF_HEADING_SIZE, &bold);
+        pages.skip(PDF_BODY_SIZE);
+        for line in summarized_text.lines() {
+            match section_title(line) {
+                Some(title) => {
+                    pages.skip(PDF_BODY_SIZE);
+                    pages.text(title, PDF_SUBHEADING_SIZE, &bold);
+                }
+                None => pages.paragraph(line, &regular),
+            }
+        }
+
+        if !turns.is_empty() {
+            pages.new_page();
+        }
+    }
+    if !turns.is_empty() {
+        pages.text("Transcription", PDF_HEADING_SIZE, &bold);
+    }
+    for turn in turns {
+        pages.skip(PDF_BODY_SIZE);
+        match turn.speaker.as_str() {
+            "" => pages.paragraph(&turn.text, &regular),
+            speaker => pages.paragraph(&format!("{}: {}", speaker, turn.text), &regular),
+        }
+    }
+
+    let file = File::create(path).map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    doc.save(&mut BufWriter::new(file))
+        .map_err(|e| anyhow::anyhow!("Error writing PDF file: {}", e))?;
+
+    Ok(())
+}
+
+// The built-in PDF fonts only have Western European characters (Latin-1, plus
+// the dashes, quotes etc. of Windows-1252), so other text, e.g. Japanese or
+// Greek, fails clearly instead of coming out garbled
+fn check_pdf_text(text: &str, what: &str) -> Result<()> {
+    const WINDOWS_1252: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+    let unsupported = text.chars().find(|&c| {
+        !(c.is_ascii() || ('\u{a0}'..='\u{ff}').contains(&c) || WINDOWS_1252.contains(c))
+    });
+
+    match unsupported {
+        Some(c) => anyhow::bail!(
+            "PDF output only supports Western European (Latin-1) text, but the {} contains '{}'. Use another output type, e.g. -o word or -o markdown.",
+            what,
+            c
+        ),
+        None => Ok(()),
+    }
+}
+
+// Lays out lines of text top to bottom, starting a new page when one fills up
+struct PdfPages<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    // Baseline of the next line, from the bottom of the page
+    y: f32,
+}
+
+impl PdfPages<'_> {
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PDF_PAGE_WIDTH), Mm(PDF_PAGE_HEIGHT), "Layer 1");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PDF_PAGE_HEIGHT - PDF_MARGIN;
+    }
+
+    // Move down by one line of text at `font_size` points
+    fn skip(&mut self, font_size: f32) {
+        // 1pt is 0.3528mm; lines are spaced at 1.4 times the font size
+        self.y -= font_size * 0.3528 * 1.4;
+    }
+
+    fn text(&mut self, text: &str, font_size: f32, font: &IndirectFontRef) {
+        self.skip(font_size);
+        if self.y < PDF_MARGIN {
+            self.new_page();
+            self.skip(font_size);
+        }
+        self.layer
+            .use_text(text, font_size, Mm(PDF_MARGIN), Mm(self.y), font);
+    }
+
+    fn paragraph(&mut self, text: &str, font: &IndirectFontRef) {
+        if text.trim().is_empty() {
+            self.skip(PDF_BODY_SIZE);
+            return;
+        }
+        for line in wrap_text(text, PDF_LINE_CHARS) {
+            self.text(&line, PDF_BODY_SIZE, font);
+        }
+    }
+}
+
+// Break `text` into lines of at most `width` characters at word boundaries,
+// splitting words that are longer than a line
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..width).collect());
+        }
+
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Write summaries from several models side by side, labeled by model, along
+// with the token usage of each. Returns the path of the written file for
+// file-based outputs.
+pub async fn write_comparison(
+    output_type: OutputType,
+    output_file: Option<&Path>,
+    summaries: &[ModelSummary],
+    transcription: &str,
+    turns: &[SpeakerTurn],
+    settings: &Settings,
+    spinner: &mut Progress,
+) -> Result<Option<PathBuf>> {
+    let transcript_turns = written_turns(transcription, turns);
+    let output_file_path = match output_type {
+        OutputType::Terminal => {
+            spinner.success("Done!");
+            spinner.note("");
+            println!("{}", comparison_text(summaries));
+            if !transcription.is_empty() {
+                println!("Transcription:\n{}\n", transcription);
+            }
+            return Ok(None);
+        }
+        OutputType::Slack => {
+            send_to_slack(&comparison_text(summaries), settings, spinner).await;
+            return Ok(None);
+        }
+        OutputType::Text => {
+            let path = output_path(output_file, "summary.txt")?;
+            let content = if transcription.is_empty() {
+                comparison_text(summaries)
+            } else {
+                format!(
+                    "{}\nTranscription:\n{}",
+                    comparison_text(summaries),
+                    transcription
+                )
+            };
+            std::fs::write(path, content)
+                .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+            path
+        }
+        OutputType::Markdown => {
+            let path = output_path(output_file, "summary.md")?;
+            let content = format!(
+                "{}{}",
+                comparison_markdown(summaries),
+                markdown_transcription(transcript_turns, "##")
+            );
+            std::fs::write(path, content)
+                .map_err(|e| anyhow::anyhow!("Error writing Markdown file: {}", e))?;
+            path
+        }
+        OutputType::Word => {
+            let path = output_path(output_file, "summary.docx")?;
+            write_word_comparison(path, summaries, transcript_turns)?;
+            path
+        }
+        OutputType::Pdf => {
+            let path = output_path(output_file, "summary.pdf")?;
+            write_pdf(path, &comparison_text(summaries), transcript_turns)?;
+            path
+        }
+        OutputType::Json => {
+            let path = output_path(output_file, "summary.json")?;
+            let summaries: Vec<serde_json::Value> = summaries
+                .iter()
+                .map(|summary| {
+                    json!({
+                        "model_id": summary.model_id,
+                        "summary": summary.text,
+                        "input_tokens": summary.input_tokens,
+                        "output_tokens": summary.output_tokens,
+                    })
+                })
+                .collect();
+            let content = serde_json::to_string_pretty(&json!({
+                "comparison": summaries,
+                "transcription": transcription,
+            }))?;
+            std::fs::write(path, content + "\n")
+                .map_err(|e| anyhow::anyhow!("Error writing JSON file: {}", e))?;
+            path
+        }
+        OutputType::Vtt | OutputType::Srt => {
+            anyhow::bail!("Subtitle output contains the transcription only, without summaries")
+        }
+    };
+
+    spinner.success("Done!");
+    let contents = if transcription.is_empty() {
+        "Model comparison"
+    } else {
+        "Model comparison and transcription"
+    };
+    spinner.note(&format!(
+        "💾 {} written to {}",
+        contents,
+        output_file_path.display()
+    ));
+    Ok(Some(output_file_path.to_path_buf()))
+}
+
+fn token_usage(summary: &ModelSummary) -> String {
+    match (summary.input_tokens, summary.output_tokens) {
+        (Some(input), Some(output)) => format!("{} input / {} output tokens", input, output),
+        _ => "token usage unavailable".to_string(),
+    }
+}
+
+fn comparison_text(summaries: &[ModelSummary]) -> String {
+    summaries
+        .iter()
+        .map(|summary| {
+            format!(
+                "Summary ({}, {}):\n{}\n",
+                summary.model_id,
+                token_usage(summary),
+                summary.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// One column per model. Table cells can't contain line breaks or unescaped
+// pipes, so those are converted.
+fn comparison_markdown(summaries: &[ModelSummary]) -> String {
+    let cell = |text: &str| text.trim().replace('|', "\\|").replace('\n', "<br>");
+    let row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+    let mut table = row(summaries.iter().map(|s| cell(&s.model_id)).collect());
+    table.push_str(&row(summaries.iter().map(|_| "---".to_string()).collect()));
+    table.push_str(&row(summaries.iter().map(|s| cell(&s.text)).collect()));
+    table.push_str(&row(summaries.iter().map(token_usage).collect()));
+
+    format!("## Summary\n\n{}", table.trim_end())
+}
+
+fn write_word_comparison(
+    path: &Path,
+    summaries: &[ModelSummary],
+    turns: &[SpeakerTurn],
+) -> Result<()> {
+    let label_cells = summaries
+        .iter()
+        .map(|summary| {
+            TableCell::new().add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(&summary.model_id).bold())
+                    .add_run(Run::new().add_text(format!(" ({})", token_usage(summary)))),
+            )
+        })
+        .collect();
+    let summary_cells = summaries
+        .iter()
+        .map(|summary| {
+            summary
+                .text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .fold(TableCell::new(), |cell, line| {
+                    cell.add_paragraph(Paragraph::new().add_run(Run::new().add_text(line)))
+                })
+        })
+        .collect();
+
+    let doc = word_styles(Docx::new())
+        .add_paragraph(word_heading("Summary", 1))
+        .add_table(Table::new(vec![
+            TableRow::new(label_cells),
+            TableRow::new(summary_cells),
+        ]));
+    let doc = add_word_transcription(doc, turns, 1);
+
+    let file = File::create(path).map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+    doc.build()
+        .pack(file)
+        .map_err(|e| anyhow::anyhow!("Error writing Word document: {}", e))?;
+
+    Ok(())
+}
+
+// Record the prompt that produced the summary in prompt.txt
+pub fn write_prompt_record(record: &PromptRecord) -> Result<PathBuf> {
+    let path = Path::new("prompt.txt");
+    std::fs::write(path, format!("{}\n", record))
+        .map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+
+    Ok(path.to_path_buf())
+}
+
+// Save Amazon Transcribe's JSON output as fetched: transcript.json, or
+// <name>.transcript.json next to --output-file
+pub fn write_raw_transcript(output_file: Option<&Path>, body: &str) -> Result<PathBuf> {
+    let path = match output_file {
+        Some(output_file) => {
+            let stem = output_file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            output_file.with_file_name(format!("{}.transcript.json", stem))
+        }
+        None => PathBuf::from("transcript.json"),
+    };
+    let path = output_path(Some(&path), "transcript.json")?.to_path_buf();
+    std::fs::write(&path, body).map_err(|e| anyhow::anyhow!("Error creating file: {}", e))?;
+
+    Ok(path)
+}
+
+async fn send_to_slack(summarized_text: &str, settings: &Settings, spinner: &mut Progress) {
+    let slack_webhook_endpoint = settings.slack.webhook_endpoint.clone().unwrap_or_default();
+
+    if slack_webhook_endpoint.is_empty() {
+        spinner.stop_and_persist(
+            "⚠️",
+            "Slack webhook endpoint is not configured. Skipping Slack notification.",
+        );
+        println!("Summary:\n{}\n", summarized_text);
+    } else {
+        let content = format!("A summarization job just completed:\n\n{}", summarized_text);
+        match notify::post_to_slack(&slack_webhook_endpoint, &content).await {
+            Ok(()) => spinner.success("Summary sent to Slack!"),
+            Err(err) => {
+                spinner.stop_and_persist("❌", "Failed to send summary to Slack!");
+                eprintln!("Error sending summary to Slack: {:#}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::transcript::speaker_turns;
+
+    #[test]
+    fn write_markdown_appends_a_dated_section_to_an_existing_file() {
+        let dir = TempDir::new();
+        let path = dir.join("series.md");
+        write_markdown(&path, "Week one.", &[], None).unwrap();
+
+        write_markdown(&path, "Week two.", &[], Some("2024-06-08 — week2.mp3")).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "## Summary\n\nWeek one.\n\n\n## 2024-06-08 — week2.mp3\n\n### Summary\n\nWeek two.\n"
+        );
+    }
+
+    #[test]
+    fn append_section_is_only_used_for_an_existing_file() {
+        let dir = TempDir::new();
+        let path = dir.join("series.md");
+        let opts = Options {
+            append: true,
+            ..Options::default()
+        };
+
+        assert_eq!(append_section(&opts, &path, "week1.mp3"), None);
+
+        std::fs::write(&path, "## Summary\n").unwrap();
+        let title = append_section(&opts, &path, "week2.mp3").unwrap();
+        // E.g. "2024-06-08 — week2.mp3"
+        assert_eq!(title.len(), "2024-06-08 — week2.mp3".len());
+        assert!(title.ends_with(" — week2.mp3"));
+        assert_eq!(
+            append_section(&Options::default(), &path, "week2.mp3"),
+            None
+        );
+    }
+
+    fn document<'a>(
+        transcription: &'a str,
+        turns: &'a [SpeakerTurn],
+        alternatives: &'a [TranscriptSegment],
+    ) -> Document<'a> {
+        Document {
+            source_name: "standup.mp3",
+            summary: "We shipped it.",
+            transcription,
+            turns,
+            alternatives,
+            job_name: "transcription-1",
+            language_code: "en-US",
+            model_id: None,
+        }
+    }
+
+    #[test]
+    fn json_document_includes_segment_alternatives() {
+        let turns = speaker_turns("spk_0: Ship it.\n");
+        let alternatives = [TranscriptSegment {
+            start: Some(0.0),
+            end: Some(0.9),
+            alternatives: vec!["Ship it.".to_string(), "Chip it.".to_string()],
+        }];
+
+        let json = json_document(&document("spk_0: Ship it.\n", &turns, &alternatives));
+
+        assert_eq!(
+            json["alternatives"],
+            json!([{"start": 0.0, "end": 0.9, "alternatives": ["Ship it.", "Chip it."]}])
+        );
+        // Left out without --keep-alternatives, or with --no-transcript
+        let json = json_document(&document("spk_0: Ship it.\n", &turns, &[]));
+        assert!(json.get("alternatives").is_none());
+        let json = json_document(&document("", &turns, &alternatives));
+        assert!(json.get("alternatives").is_none());
+    }
+
+    fn turn(speaker: &str, text: &str, start: Option<f64>, end: Option<f64>) -> SpeakerTurn {
+        SpeakerTurn {
+            speaker: speaker.to_string(),
+            start,
+            end,
+            ..SpeakerTurn::unlabeled(text)
+        }
+    }
+
+    #[test]
+    fn webvtt_writes_a_cue_with_a_voice_span_per_timed_turn() {
+        let turns = [
+            turn("spk_0", "Hello <everyone> & welcome", Some(0.0), Some(2.5)),
+            // Without timings a turn can't be cued
+            turn("spk_1", "Hi", None, None),
+            turn("", "Thanks.", Some(3723.4567), Some(3725.0)),
+        ];
+
+        assert_eq!(
+            webvtt(&turns),
+            "WEBVTT\n\
+             \n\
+             1\n\
+             00:00:00.000 --> 00:00:02.500\n\
+             <v spk_0>Hello &lt;everyone&gt; &amp; welcome</v>\n\
+             \n\
+             2\n\
+             01:02:03.457 --> 01:02:05.000\n\
+             Thanks.\n"
+        );
+    }
+
+    #[test]
+    fn markdown_document_writes_each_turn_as_spoken() {
+        let turns = [
+            turn("spk_0", "Okay: spk_1 is the label for Ana.", None, None),
+            turn("spk_1", "Thanks.", None, None),
+        ];
+
+        assert_eq!(
+            markdown_document("Labels explained.", &turns),
+            "## Summary\n\nLabels explained.\n\n## Transcription\n\n\
+             | Speaker | Turns | Words |\n| --- | ---: | ---: |\n| spk_0 | 1 | 7 |\n| spk_1 | 1 | 1 |\n\n\
+             **spk_0:** Okay: spk_1 is the label for Ana.\n\n\
+             **spk_1:** Thanks.\n"
+        );
+    }
+
+    #[test]
+    fn markdown_document_leaves_out_an_empty_transcription() {
+        assert_eq!(
+            markdown_document("Week one.", &[]),
+            "## Summary\n\nWeek one.\n"
+        );
+    }
+
+    #[test]
+    fn write_pdf_rejects_text_the_built_in_font_cannot_show() {
+        let dir = TempDir::new();
+        let path = dir.join("summary.pdf");
+
+        let err =
+            write_pdf(&path, "Summary", &speaker_turns("spk_0: 会議を始めます\n")).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("PDF output only supports Western European (Latin-1) text, but the transcription contains '会'"));
+        assert!(!path.exists());
+        check_pdf_text("Café — “ready” … 5 €", "summary").unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+
+use spinoff::{spinners, Color, Spinner};
+
+// The spinner and progress lines shown while a run is in progress. With
+// --quiet there is no spinner and progress lines are left out, but warnings
+// are still printed, to stderr.
+pub struct Progress {
+    spinner: Option<Spinner>,
+}
+
+impl Progress {
+    pub fn new(text: impl Into<Cow<'static, str>>, quiet: bool) -> Self {
+        Self {
+            spinner: (!quiet).then(|| Spinner::new(spinners::Dots7, text, Color::Green)),
+        }
+    }
+
+    pub fn update(&mut self, text: impl Into<Cow<'static, str>>) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.update(spinners::Dots7, text, None);
+        }
+    }
+
+    pub fn update_text(&mut self, text: impl Into<Cow<'static, str>>) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.update_text(text);
+        }
+    }
+
+    pub fn success(&mut self, text: &str) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.success(text);
+        }
+    }
+
+    pub fn warn(&mut self, text: &str) {
+        match &mut self.spinner {
+            Some(spinner) => spinner.warn(text),
+            None => eprintln!("⚠️  {}", text),
+        }
+    }
+
+    // The error that follows says what went wrong, so quiet runs print
+    // nothing here
+    pub fn fail(&mut self, text: &str) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.fail(text);
+        }
+    }
+
+    pub fn stop_and_persist(&mut self, symbol: &str, text: &str) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.stop_and_persist(symbol, text);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(spinner) = &mut self.spinner {
+            spinner.clear();
+        }
+    }
+
+    // Print a progress line, e.g. "💾 Summary written to summary.md"
+    pub fn note(&self, text: &str) {
+        if self.spinner.is_some() {
+            println!("{}", text);
+        }
+    }
+}
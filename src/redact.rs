@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use aws_sdk_comprehend::types::LanguageCode;
+use aws_sdk_comprehend::Client;
+
+// A detected PII entity. Offsets are character (not byte) offsets into the
+// text, matching what Amazon Comprehend returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiiSpan {
+    pub begin: usize,
+    pub end: usize,
+    pub entity_type: String,
+}
+
+// Mask PII in `text` using Amazon Comprehend. Only entities whose type is
+// listed in `entity_types` are masked; an empty list masks every type.
+pub async fn redact_text(
+    client: &Client,
+    text: &str,
+    language_code: &str,
+    entity_types: &[String],
+) -> Result<String> {
+    // Comprehend's PII detection only supports English and Spanish
+    let comprehend_language = if language_code.starts_with("es") {
+        LanguageCode::Es
+    } else {
+        LanguageCode::En
+    };
+
+    let resp = client
+        .detect_pii_entities()
+        .text(text)
+        .language_code(comprehend_language)
+        .send()
+        .await
+        .context("Failed to detect PII entities with Amazon Comprehend")?;
+
+    let spans: Vec<PiiSpan> = resp
+        .entities()
+        .iter()
+        .filter_map(|entity| {
+            Some(PiiSpan {
+                begin: entity.begin_offset()? as usize,
+                end: entity.end_offset()? as usize,
+                entity_type: entity.r#type()?.as_str().to_string(),
+            })
+        })
+        .filter(|span| {
+            entity_types.is_empty()
+                || entity_types
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(&span.entity_type))
+        })
+        .collect();
+
+    Ok(mask_entities(text, &spans))
+}
+
+// Replace each span with its entity type, e.g. "Call Jane" becomes
+// "Call [NAME]". Spans that overlap an earlier span are ignored.
+pub fn mask_entities(text: &str, spans: &[PiiSpan]) -> String {
+    let mut spans = spans.to_vec();
+    spans.sort_by_key(|span| span.begin);
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut masked = String::with_capacity(text.len());
+    let mut position = 0;
+
+    for span in spans {
+        let begin = span.begin.min(chars.len());
+        let end = span.end.min(chars.len());
+        if begin < position || begin >= end {
+            continue;
+        }
+
+        masked.extend(&chars[position..begin]);
+        masked.push_str(&format!("[{}]", span.entity_type));
+        position = end;
+    }
+    masked.extend(&chars[position..]);
+
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(begin: usize, end: usize, entity_type: &str) -> PiiSpan {
+        PiiSpan {
+            begin,
+            end,
+            entity_type: entity_type.to_string(),
+        }
+    }
+
+    #[test]
+    fn mask_entities_replaces_spans_with_their_type() {
+        let masked = mask_entities("Call Jane at 555-0100", &[span(5, 9, "NAME")]);
+
+        assert_eq!(masked, "Call [NAME] at 555-0100");
+    }
+
+    #[test]
+    fn mask_entities_uses_character_offsets_after_multibyte_text() {
+        // "Café ☕ " is 7 characters but 10 bytes
+        let text = "Café ☕ with José";
+
+        let masked = mask_entities(text, &[span(12, 16, "NAME")]);
+
+        assert_eq!(masked, "Café ☕ with [NAME]");
+    }
+
+    #[test]
+    fn mask_entities_masks_adjacent_entities_separately() {
+        let masked = mask_entities(
+            "JaneDoe@example.com",
+            &[span(7, 19, "EMAIL"), span(0, 7, "NAME")],
+        );
+
+        assert_eq!(masked, "[NAME][EMAIL]");
+    }
+
+    #[test]
+    fn mask_entities_skips_spans_overlapping_an_earlier_one() {
+        let masked = mask_entities(
+            "Jane Doe, 12 Main St",
+            &[span(0, 8, "NAME"), span(5, 20, "ADDRESS")],
+        );
+
+        assert_eq!(masked, "[NAME], 12 Main St");
+    }
+
+    #[test]
+    fn mask_entities_clamps_spans_past_the_end() {
+        let masked = mask_entities("Hi Jane", &[span(3, 40, "NAME")]);
+
+        assert_eq!(masked, "Hi [NAME]");
+    }
+}
@@ -0,0 +1,569 @@
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use aws_config::{Region, SdkConfig};
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::primitives::{ByteStream, Length};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, ServerSideEncryption};
+use aws_sdk_s3::Client;
+use aws_smithy_types::body::SdkBody;
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::progress::Progress;
+use crate::settings::Settings;
+
+const MIB: u64 = 1024 * 1024;
+// S3 requires every part but the last to be at least 5 MiB
+const MIN_PART_SIZE: u64 = 5 * MIB;
+// S3 allows at most 10,000 parts per upload
+const MAX_PARTS: u64 = 10_000;
+// How often the spinner shows how much of a single-request upload was sent
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// An S3 client for `config`, honoring s3.endpoint_url and s3.force_path_style
+pub fn client(config: &SdkConfig, settings: &Settings) -> Client {
+    let mut builder = aws_sdk_s3::config::Builder::from(config);
+    // Only override the endpoint from `config` (e.g. --endpoint-url) when set
+    if let Some(endpoint_url) = &settings.s3.endpoint_url {
+        builder.set_endpoint_url(Some(endpoint_url.clone()));
+    }
+
+    Client::from_conf(
+        builder
+            .force_path_style(settings.s3.force_path_style)
+            .build(),
+    )
+}
+
+// Split an "s3://bucket/key" URI into its bucket and key
+pub fn parse_s3_uri(uri: &str) -> Result<(String, String)> {
+    let (bucket, key) = uri
+        .strip_prefix("s3://")
+        .and_then(|path| path.split_once('/'))
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .with_context(|| format!("Invalid S3 URI {}; expected s3://bucket/key", uri))?;
+
+    Ok((bucket.to_string(), key.to_string()))
+}
+
+// Split an "s3://bucket/prefix/" URI into its bucket and (possibly empty) key
+// prefix
+pub fn parse_s3_prefix(uri: &str) -> Result<(String, String)> {
+    let path = uri
+        .strip_prefix("s3://")
+        .with_context(|| format!("Invalid S3 URI {}; expected s3://bucket/prefix/", uri))?;
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    if bucket.is_empty() {
+        bail!("Invalid S3 URI {}; expected s3://bucket/prefix/", uri);
+    }
+
+    Ok((bucket.to_string(), prefix.to_string()))
+}
+
+pub async fn list_buckets(client: &Client) -> Result<Vec<String>> {
+    let resp = client.list_buckets().send().await?;
+    let buckets = resp.buckets();
+
+    let bucket_names: Vec<String> = buckets
+        .iter()
+        .map(|bucket| bucket.name().unwrap_or_default().to_string())
+        .collect();
+
+    Ok(bucket_names)
+}
+
+// The region `bucket_name` is in. Without the s3:GetBucketLocation permission
+// (which many policies that allow uploads leave out), the client's region is
+// assumed instead, or us-east-1 if it has none.
+pub async fn bucket_region(client: &Client, bucket_name: &str) -> Result<Region> {
+    let resp = match client
+        .get_bucket_location()
+        .bucket(bucket_name)
+        .send()
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err) if err.code() == Some("AccessDenied") => {
+            let region = client
+                .config()
+                .region()
+                .cloned()
+                .unwrap_or_else(|| Region::new("us-east-1"));
+            eprintln!(
+                "⚠️  Unable to look up the region of bucket {} (s3:GetBucketLocation was denied); assuming {}",
+                bucket_name, region
+            );
+            return Ok(region);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let location_constraint = resp
+        .location_constraint()
+        .context("Bucket has no location_constraint")?;
+
+    if location_constraint.as_str() == "" {
+        Ok(Region::new("us-east-1"))
+    } else {
+        Ok(Region::new(location_constraint.as_str().to_owned()))
+    }
+}
+
+// The key for `file_name` under `prefix`, e.g. "distill/incoming/meeting.mp3"
+// for the prefix "/distill/incoming/". Empty segments in the prefix are
+// dropped so the key never has doubled or leading separators.
+pub fn object_key(prefix: &str, file_name: &str) -> String {
+    prefix
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .chain(std::iter::once(file_name))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Objects are encrypted with SSE-KMS when aws.kms_key_id is set, and with the
+// bucket's default encryption otherwise. Files larger than
+// s3.multipart_threshold_mb are uploaded part by part; either way progress is
+// reported on `spinner`.
+pub async fn upload_file(
+    client: &Client,
+    bucket_name: &str,
+    key: &str,
+    path: &Path,
+    settings: &Settings,
+    mut spinner: Option<&mut Progress>,
+) -> Result<()> {
+    let kms_key_id = settings.aws.kms_key_id.as_deref();
+    let size = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("Error loading file: {}", path.display()))?
+        .len();
+    let started = Instant::now();
+    if size > settings.s3.multipart_threshold_mb * MIB {
+        upload_parts(client, bucket_name, key, path, settings, size, spinner).await?;
+    } else {
+        let sent = Arc::new(AtomicU64::new(0));
+        let body = ByteStream::from_path(path)
+            .await
+            .with_context(|| format!("Error loading file: {}", path.display()))?
+            .map(progress_body(sent.clone()));
+
+        let upload = client
+            .put_object()
+            .bucket(bucket_name)
+            .key(key)
+            .body(body)
+            .set_server_side_encryption(kms_key_id.map(|_| ServerSideEncryption::AwsKms))
+            .set_ssekms_key_id(kms_key_id.map(str::to_string))
+            .set_tagging(object_tagging(settings))
+            .customize()
+            .config_override(upload_retries(settings))
+            .send();
+        let _upload_result = with_progress(upload, &sent, |sent| {
+            if let Some(spinner) = spinner.as_deref_mut() {
+                if size > 0 {
+                    spinner.update_text(upload_progress(sent, size));
+                }
+            }
+        })
+        .await
+        .context("Failed to upload to S3")?;
+    }
+    debug!(
+        "Uploaded {} ({} bytes) to s3://{}/{} in {:.1?}",
+        path.display(),
+        size,
+        bucket_name,
+        key,
+        started.elapsed()
+    );
+
+    Ok(())
+}
+
+// Wraps a request body so the bytes read from it are counted in `sent`. The
+// count starts over when the body is rebuilt to retry the request.
+fn progress_body(sent: Arc<AtomicU64>) -> impl Fn(SdkBody) -> SdkBody + Send + Sync + 'static {
+    move |body| {
+        sent.store(0, Ordering::Relaxed);
+        SdkBody::from_body_1_x(ProgressBody {
+            inner: body,
+            sent: sent.clone(),
+        })
+    }
+}
+
+struct ProgressBody {
+    inner: SdkBody,
+    sent: Arc<AtomicU64>,
+}
+
+impl Body for ProgressBody {
+    type Data = Bytes;
+    type Error = <SdkBody as Body>::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        Body::is_end_stream(&self.inner)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        Body::size_hint(&self.inner)
+    }
+}
+
+// Drive `upload` to completion, passing the bytes `sent` so far to `report`
+// every PROGRESS_INTERVAL
+async fn with_progress<T>(
+    upload: impl Future<Output = T>,
+    sent: &AtomicU64,
+    mut report: impl FnMut(u64),
+) -> T {
+    let mut upload = std::pin::pin!(upload);
+    let mut ticks = tokio::time::interval(PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            result = &mut upload => return result,
+            _ = ticks.tick() => report(sent.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+// Upload `size` bytes from `path` with a multipart upload, aborting it if any
+// part fails so S3 doesn't keep (and bill for) the parts already uploaded
+async fn upload_parts(
+    client: &Client,
+    bucket_name: &str,
+    key: &str,
+    path: &Path,
+    settings: &Settings,
+    size: u64,
+    mut spinner: Option<&mut Progress>,
+) -> Result<()> {
+    let kms_key_id = settings.aws.kms_key_id.as_deref();
+    let part_size = (settings.s3.part_size_mb * MIB)
+        .max(MIN_PART_SIZE)
+        .max(size.div_ceil(MAX_PARTS));
+    debug!(
+        "Uploading {} bytes in {} parts of {} bytes",
+        size,
+        size.div_ceil(part_size),
+        part_size
+    );
+
+    let upload = client
+        .create_multipart_upload()
+        .bucket(bucket_name)
+        .key(key)
+        .set_server_side_encryption(kms_key_id.map(|_| ServerSideEncryption::AwsKms))
+        .set_ssekms_key_id(kms_key_id.map(str::to_string))
+        .set_tagging(object_tagging(settings))
+        .customize()
+        .config_override(upload_retries(settings))
+        .send()
+        .await
+        .context("Failed to upload to S3")?;
+    let upload_id = upload
+        .upload_id()
+        .context("S3 did not return a multipart upload ID")?;
+
+    let mut parts = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        let length = part_size.min(size - offset);
+        let part_number = parts.len() as i32 + 1;
+        let part = async {
+            let body = ByteStream::read_from()
+                .path(path)
+                .offset(offset)
+                .length(Length::Exact(length))
+                .build()
+                .await
+                .with_context(|| format!("Error loading file: {}", path.display()))?;
+
+            client
+                .upload_part()
+                .bucket(bucket_name)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(body)
+                .customize()
+                .config_override(upload_retries(settings))
+                .send()
+                .await
+                .with_context(|| format!("Failed to upload part {} to S3", part_number))
+        }
+        .await;
+
+        let part = match part {
+            Ok(part) => part,
+            Err(err) => {
+                abort_upload(client, bucket_name, key, upload_id).await;
+                return Err(err);
+            }
+        };
+        parts.push(
+            CompletedPart::builder()
+                .set_e_tag(part.e_tag().map(str::to_string))
+                .part_number(part_number)
+                .build(),
+        );
+
+        offset += length;
+        if let Some(spinner) = spinner.as_deref_mut() {
+            spinner.update_text(upload_progress(offset, size));
+        }
+    }
+
+    if let Err(err) = client
+        .complete_multipart_upload()
+        .bucket(bucket_name)
+        .key(key)
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build(),
+        )
+        .customize()
+        .config_override(upload_retries(settings))
+        .send()
+        .await
+    {
+        abort_upload(client, bucket_name, key, upload_id).await;
+        return Err(err).context("Failed to complete the upload to S3");
+    }
+
+    Ok(())
+}
+
+async fn abort_upload(client: &Client, bucket_name: &str, key: &str, upload_id: &str) {
+    if let Err(err) = client
+        .abort_multipart_upload()
+        .bucket(bucket_name)
+        .key(key)
+        .upload_id(upload_id)
+        .send()
+        .await
+    {
+        eprintln!(
+            "⚠️  Unable to abort the upload to s3://{}/{}: {}",
+            bucket_name, key, err
+        );
+    }
+}
+
+// e.g. "Uploading file to S3... 45% (225.0 of 500.0 MB)"
+fn upload_progress(uploaded: u64, size: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+
+    format!(
+        "Uploading file to S3... {}% ({:.1} of {:.1} MB)",
+        uploaded * 100 / size,
+        uploaded as f64 / MB,
+        size as f64 / MB
+    )
+}
+
+pub async fn put_text(
+    client: &Client,
+    bucket_name: &str,
+    key: &str,
+    body: String,
+    settings: &Settings,
+) -> Result<()> {
+    let kms_key_id = settings.aws.kms_key_id.as_deref();
+    client
+        .put_object()
+        .bucket(bucket_name)
+        .key(key)
+        .body(ByteStream::from(body.into_bytes()))
+        .set_server_side_encryption(kms_key_id.map(|_| ServerSideEncryption::AwsKms))
+        .set_ssekms_key_id(kms_key_id.map(str::to_string))
+        .set_tagging(object_tagging(settings))
+        .customize()
+        .config_override(upload_retries(settings))
+        .send()
+        .await
+        .with_context(|| format!("Failed to write s3://{}/{}", bucket_name, key))?;
+
+    Ok(())
+}
+
+// Upload requests are retried up to aws.upload_max_retries times, with
+// exponential backoff, when they fail with a timeout, a network error, a 5xx
+// or throttling. Errors such as access denied fail straight away. The file is
+// re-read for each attempt, and each part of a multipart upload is retried on
+// its own.
+fn upload_retries(settings: &Settings) -> aws_sdk_s3::config::Builder {
+    aws_sdk_s3::config::Builder::new().retry_config(
+        RetryConfig::standard().with_max_attempts(settings.aws.upload_max_retries + 1),
+    )
+}
+
+// aws.tags as the URL-encoded query string S3 expects for object tags, e.g.
+// "CostCenter=1234&Team=Data%20Science"
+fn object_tagging(settings: &Settings) -> Option<String> {
+    if settings.aws.tags.is_empty() {
+        return None;
+    }
+
+    let tagging = settings
+        .aws
+        .tags
+        .iter()
+        .map(|(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    Some(tagging)
+}
+
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub async fn delete_object(client: &Client, bucket_name: &str, key: &str) -> Result<()> {
+    client
+        .delete_object()
+        .bucket(bucket_name)
+        .key(key)
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{mock_aws, MockHttp, MockResponse, TempDir};
+
+    #[tokio::test]
+    async fn client_uses_virtual_hosted_addressing_by_default() {
+        let mock = mock_aws("{}", "");
+        let client = client(&mock.sdk_config().await, &Settings::default());
+
+        bucket_region(&client, "meetings").await.unwrap();
+
+        assert!(mock.requests()[0]
+            .uri
+            .starts_with("https://meetings.s3.us-east-1.amazonaws.com/?location"));
+    }
+
+    #[tokio::test]
+    async fn client_honors_the_endpoint_and_path_style_addressing() {
+        let mock = mock_aws("{}", "");
+        let mut settings = Settings::default();
+        settings.s3.endpoint_url = Some("http://localhost:4566".to_string());
+        settings.s3.force_path_style = true;
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let region = bucket_region(&client, "meetings").await.unwrap();
+
+        assert_eq!(region.as_ref(), "us-west-2");
+        assert!(mock.requests()[0]
+            .uri
+            .starts_with("http://localhost:4566/meetings/?location"));
+    }
+
+    #[tokio::test]
+    async fn upload_file_sends_the_file_in_one_request() {
+        let mock = mock_aws("{}", "");
+        let client = client(&mock.sdk_config().await, &Settings::default());
+        let dir = TempDir::new();
+        let path = dir.join("standup.mp3");
+        std::fs::write(&path, "not really audio").unwrap();
+
+        upload_file(
+            &client,
+            "meetings",
+            "standup.mp3",
+            &path,
+            &Settings::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        // Sent aws-chunked, with a checksum trailer
+        assert!(requests[0].body.contains("\r\nnot really audio\r\n"));
+    }
+
+    #[tokio::test]
+    async fn progress_body_counts_the_bytes_read() {
+        let sent = Arc::new(AtomicU64::new(0));
+        let body = ByteStream::from_static(b"0123456789").map(progress_body(sent.clone()));
+
+        assert_eq!(body.size_hint(), (10, Some(10)));
+        let data = body.collect().await.unwrap().into_bytes();
+
+        assert_eq!(&data[..], b"0123456789");
+        assert_eq!(sent.load(Ordering::Relaxed), 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn with_progress_reports_while_the_upload_runs() {
+        // The mock reads the whole body, then takes a second to answer
+        let mock = MockHttp::new(|_| MockResponse::ok("").delayed(Duration::from_secs(1)));
+        let client = client(&mock.sdk_config().await, &Settings::default());
+        let sent = Arc::new(AtomicU64::new(0));
+        let body = ByteStream::from_static(b"0123456789").map(progress_body(sent.clone()));
+        let upload = client
+            .put_object()
+            .bucket("meetings")
+            .key("standup.mp3")
+            .body(body)
+            .send();
+
+        let mut reports = Vec::new();
+        with_progress(upload, &sent, |sent| reports.push(sent))
+            .await
+            .unwrap();
+
+        // Reported every PROGRESS_INTERVAL until the response arrives
+        assert_eq!(reports.last(), Some(&10));
+        assert!(reports.len() >= 4);
+    }
+
+    #[test]
+    fn upload_progress_shows_the_percentage_sent() {
+        assert_eq!(
+            upload_progress(225 * MIB, 500 * MIB),
+            "Uploading file to S3... 45% (225.0 of 500.0 MB)"
+        );
+    }
+}
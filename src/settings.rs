@@ -0,0 +1,696 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use config::{Config, File};
+use serde::Deserialize;
+
+use crate::summarize::{ModelFamily, SummaryStyle};
+use crate::transcribe::{FilterMethod, InputMediaFormat};
+
+/// Typed view of config.toml. Every section and key is optional so that a
+/// partially filled-in config behaves the same as before: missing values fall
+/// back to their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub aws: AwsSettings,
+    pub model: ModelSettings,
+    pub anthropic: AnthropicSettings,
+    pub prompt: PromptSettings,
+    pub slack: SlackSettings,
+    pub notify: NotifySettings,
+    pub hooks: HooksSettings,
+    pub audit: AuditSettings,
+    pub redaction: RedactionSettings,
+    pub s3: S3Settings,
+    pub transcribe: TranscribeSettings,
+    pub bedrock: BedrockSettings,
+    pub retry: RetrySettings,
+    pub sections: SectionsSettings,
+    pub worker: WorkerSettings,
+    pub profiles: BTreeMap<String, ProfileSettings>,
+    /// Named templates selected with --prompt, e.g. `[prompts] executive = "..."`
+    pub prompts: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AwsSettings {
+    pub s3_bucket_name: Option<String>,
+    /// Key prefix for uploaded audio, e.g. "distill/incoming/"
+    pub s3_key_prefix: Option<String>,
+    /// KMS key to encrypt uploaded objects with (SSE-KMS), as an ID, ARN or
+    /// alias; unset uses the bucket's default encryption
+    pub kms_key_id: Option<String>,
+    /// Tags for the uploaded objects and transcription jobs, e.g. for cost
+    /// allocation or lifecycle rules. --tag adds to these.
+    pub tags: BTreeMap<String, String>,
+    /// How many times to retry an upload request (or a part of a multipart
+    /// upload) that failed with a timeout, network or 5xx error
+    pub upload_max_retries: u32,
+}
+
+impl Default for AwsSettings {
+    fn default() -> Self {
+        Self {
+            s3_bucket_name: None,
+            s3_key_prefix: None,
+            kms_key_id: None,
+            tags: BTreeMap::new(),
+            upload_max_retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModelSettings {
+    pub model_id: String,
+    pub max_tokens: i64,
+    pub temperature: f64,
+    pub top_p: f64,
+    pub top_k: i64,
+    /// Transcripts longer than this many characters are summarized in parts,
+    /// then combined; 0 never splits them
+    pub max_input_chars: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AnthropicSettings {
+    pub anthropic_version: String,
+    pub system: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PromptSettings {
+    pub template: String,
+    /// File whose contents replace the inline template, relative to the config
+    /// file
+    pub template_file: Option<PathBuf>,
+    /// Appends a length/format instruction to the template, like --style
+    pub style: Option<SummaryStyle>,
+    /// The file the template was loaded from, if not config.toml
+    #[serde(skip)]
+    pub template_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SlackSettings {
+    pub webhook_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotifySettings {
+    /// SES-verified sender address for --email-to
+    pub ses_from: Option<String>,
+    /// Post to slack.webhook_endpoint when a run is done, unless
+    /// --slack-webhook is given
+    pub slack: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditSettings {
+    /// Append-only JSON lines log with a record of every run
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksSettings {
+    /// Command run on each written output file, unless --post-hook is given
+    pub post_command: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RedactionSettings {
+    /// Comprehend PII entity types to mask in the summary; empty masks all
+    pub summary_entity_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct S3Settings {
+    /// Custom endpoint, e.g. a VPC endpoint or LocalStack
+    pub endpoint_url: Option<String>,
+    /// Use path-style addressing, which LocalStack typically needs
+    pub force_path_style: bool,
+    /// Delete uploaded audio once it has been transcribed, like --cleanup
+    pub cleanup: bool,
+    /// Files larger than this many MiB are uploaded with a multipart upload
+    pub multipart_threshold_mb: u64,
+    /// Size of each part of a multipart upload, in MiB (at least 5)
+    pub part_size_mb: u64,
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        Self {
+            endpoint_url: None,
+            force_path_style: false,
+            cleanup: false,
+            multipart_threshold_mb: 100,
+            part_size_mb: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TranscribeSettings {
+    /// Media format to assume when neither the file contents nor its extension
+    /// identify one
+    pub default_media_format: Option<InputMediaFormat>,
+    /// Transcripts with fewer words than this are reported as (nearly) empty
+    pub min_transcript_words: usize,
+    /// Custom endpoint, e.g. a VPC endpoint
+    pub endpoint_url: Option<String>,
+    /// Candidate languages for automatic language identification
+    pub language_options: Vec<String>,
+    /// Longest wait between job status checks
+    pub poll_max_seconds: u64,
+    /// How long to wait for a transcription job before giving up
+    pub timeout_secs: u64,
+    /// How many times to check a job's status before giving up
+    pub max_polls: u32,
+    /// PII entity types masked with --redact-pii; empty masks all
+    pub pii_entity_types: Vec<String>,
+    /// Custom vocabulary of product names, acronyms, etc. to use for every job
+    pub vocabulary_name: Option<String>,
+    /// Vocabulary filter of words to mask, remove or tag in every job, e.g.
+    /// profanity
+    pub vocabulary_filter_name: Option<String>,
+    pub vocabulary_filter_method: FilterMethod,
+    /// Label who is speaking; without labels the transcript is one unlabeled
+    /// block of text
+    pub show_speaker_labels: bool,
+    /// Most speakers to tell apart, unless --speakers-expected is given
+    pub max_speaker_labels: i32,
+    /// Named job settings, e.g. `[transcribe.profiles.interview]`, selected with
+    /// --transcribe-profile
+    pub profiles: BTreeMap<String, TranscribeProfile>,
+}
+
+impl Default for TranscribeSettings {
+    fn default() -> Self {
+        Self {
+            default_media_format: None,
+            min_transcript_words: 10,
+            endpoint_url: None,
+            language_options: Vec::new(),
+            poll_max_seconds: 30,
+            timeout_secs: 7200,
+            max_polls: 500,
+            pii_entity_types: Vec::new(),
+            vocabulary_name: None,
+            vocabulary_filter_name: None,
+            vocabulary_filter_method: FilterMethod::Mask,
+            show_speaker_labels: true,
+            max_speaker_labels: 10,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BedrockSettings {
+    /// How long to wait for a summarization call before giving up
+    pub timeout_secs: u64,
+    /// Custom endpoint, e.g. a VPC endpoint
+    pub endpoint_url: Option<String>,
+    /// Region to call Bedrock in, if not the default region
+    pub region: Option<String>,
+    /// Named AWS profile for Bedrock's credentials (and region), e.g. when
+    /// Bedrock is in a different account from the S3 bucket
+    pub profile: Option<String>,
+    /// Maximum number of summarization calls in flight at once
+    pub max_concurrent: usize,
+    /// Maximum number of summarization calls started per minute
+    pub requests_per_minute: Option<u32>,
+    /// How many times to retry a throttled or otherwise transient failure
+    pub max_retries: u32,
+}
+
+impl Default for BedrockSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 300,
+            endpoint_url: None,
+            region: None,
+            profile: None,
+            max_concurrent: 4,
+            requests_per_minute: None,
+            max_retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// Retry a timed out Bedrock call once
+    pub on_timeout: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SectionsSettings {
+    /// Section titles used with --structured-sections, e.g. "Action Items"
+    pub titles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorkerSettings {
+    /// SQS queue receiving S3 ObjectCreated notifications for `distill worker`
+    pub queue_url: Option<String>,
+    /// Maximum number of recordings processed at once
+    pub concurrency: usize,
+    /// How long a received message stays hidden from other workers. It is
+    /// extended while the recording is processed, so this is how soon a failed
+    /// recording is retried.
+    pub visibility_timeout_secs: i32,
+    /// Key prefix for results written back to the recording's bucket
+    pub output_prefix: String,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            queue_url: None,
+            concurrency: 2,
+            visibility_timeout_secs: 3600,
+            output_prefix: "distill/".to_string(),
+        }
+    }
+}
+
+/// A named use-case, e.g. `[profiles.legal]`, selected with --profile-name. Any
+/// value that is set overrides the corresponding base setting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileSettings {
+    pub model_id: Option<String>,
+    pub max_tokens: Option<i64>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<i64>,
+    pub system: Option<String>,
+    pub template: Option<String>,
+}
+
+/// Alternative transcription job settings, e.g. `[transcribe.profiles.lecture]`
+/// for a single speaker. Any value that is set overrides the corresponding
+/// `[transcribe]` setting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TranscribeProfile {
+    pub show_speaker_labels: Option<bool>,
+    pub max_speaker_labels: Option<i32>,
+    pub vocabulary_name: Option<String>,
+    pub vocabulary_filter_name: Option<String>,
+    pub vocabulary_filter_method: Option<FilterMethod>,
+}
+
+impl Settings {
+    /// Load settings from a config file, e.g. "./config.toml".
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut settings: Self = Config::builder()
+            .add_source(File::from(path))
+            .build()
+            .and_then(Config::try_deserialize)
+            .with_context(|| format!("Unable to load config file {}", path.display()))?;
+
+        if let Some(template_file) = settings.prompt.template_file.clone() {
+            let template_file =
+                PathBuf::from(shellexpand::tilde(&template_file.to_string_lossy()).as_ref());
+            let dir = path.parent().unwrap_or(Path::new("."));
+            settings.load_prompt_file(&dir.join(template_file))?;
+        }
+
+        Ok(settings)
+    }
+
+    /// The config file to use without --config: ./config.toml if there is one,
+    /// otherwise distill/config.toml under $XDG_CONFIG_HOME (or ~/.config).
+    pub fn default_path() -> PathBuf {
+        let local = PathBuf::from("./config.toml");
+        if local.exists() {
+            return local;
+        }
+
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.config").as_ref()));
+        let user = config_home.join("distill").join("config.toml");
+        if user.exists() {
+            user
+        } else {
+            local
+        }
+    }
+
+    /// Check that the settings every summary needs are present, so a missing
+    /// key is reported by name instead of as a cryptic Bedrock error.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if self.model.model_id.trim().is_empty() {
+            problems.push("model_id is missing under [model]".to_string());
+        }
+        if self.model.max_tokens <= 0 {
+            problems.push("max_tokens under [model] must be a positive number".to_string());
+        }
+        if self.prompt.template.trim().is_empty() {
+            problems.push(match &self.prompt.template_path {
+                Some(path) => format!("the prompt file {} is empty", path.display()),
+                None => "template is missing under [prompt]".to_string(),
+            });
+        }
+        if ModelFamily::from_model_id(&self.model.model_id) == ModelFamily::Anthropic
+            && self.anthropic.anthropic_version.trim().is_empty()
+        {
+            problems.push(
+                "anthropic_version is missing under [anthropic] (e.g. \"bedrock-2023-05-31\")"
+                    .to_string(),
+            );
+        }
+        if self.notify.slack && self.slack.webhook_endpoint.is_none() {
+            problems
+                .push("slack under [notify] requires webhook_endpoint under [slack]".to_string());
+        }
+
+        if !problems.is_empty() {
+            bail!("{}", problems.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    /// Use the contents of a (e.g. versioned) prompt file as the template.
+    pub fn load_prompt_file(&mut self, path: &Path) -> Result<()> {
+        self.prompt.template = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read prompt file {}", path.display()))?;
+        self.prompt.template_path = Some(path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Use a named template from `[prompts]` instead of prompt.template.
+    pub fn use_prompt(&mut self, name: &str) -> Result<()> {
+        // Names are matched case-insensitively, e.g. --prompt Standup
+        let Some(template) = self
+            .prompts
+            .iter()
+            .find_map(|(key, template)| key.eq_ignore_ascii_case(name).then_some(template))
+        else {
+            let available: Vec<&str> = self.prompts.keys().map(String::as_str).collect();
+            if available.is_empty() {
+                bail!(
+                    "Unknown prompt '{}': no prompts are configured under [prompts]",
+                    name
+                );
+            }
+            bail!(
+                "Unknown prompt '{}'. Available prompts: {}",
+                name,
+                available.join(", ")
+            );
+        };
+
+        self.prompt.template = template.clone();
+        self.prompt.template_path = None;
+
+        Ok(())
+    }
+
+    /// The effective settings for a named profile: the profile merged over the
+    /// base settings.
+    pub fn with_profile(&self, name: &str) -> Result<Self> {
+        // Names are matched case-insensitively, e.g. --profile-name Legal
+        let Some(profile) = self
+            .profiles
+            .iter()
+            .find_map(|(key, profile)| key.eq_ignore_ascii_case(name).then_some(profile))
+        else {
+            let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if available.is_empty() {
+                bail!("Unknown profile '{}': no profiles are configured", name);
+            }
+            bail!(
+                "Unknown profile '{}'. Available profiles: {}",
+                name,
+                available.join(", ")
+            );
+        };
+
+        let mut settings = self.clone();
+        if let Some(model_id) = &profile.model_id {
+            settings.model.model_id = model_id.clone();
+        }
+        if let Some(max_tokens) = profile.max_tokens {
+            settings.model.max_tokens = max_tokens;
+        }
+        if let Some(temperature) = profile.temperature {
+            settings.model.temperature = temperature;
+        }
+        if let Some(top_p) = profile.top_p {
+            settings.model.top_p = top_p;
+        }
+        if let Some(top_k) = profile.top_k {
+            settings.model.top_k = top_k;
+        }
+        if let Some(system) = &profile.system {
+            settings.anthropic.system = system.clone();
+        }
+        if let Some(template) = &profile.template {
+            settings.prompt.template = template.clone();
+            settings.prompt.template_path = None;
+        }
+
+        Ok(settings)
+    }
+
+    /// Apply a named transcription profile from `[transcribe.profiles]` over the
+    /// `[transcribe]` settings.
+    pub fn use_transcribe_profile(&mut self, name: &str) -> Result<()> {
+        // Names are matched case-insensitively, e.g. --transcribe-profile Lecture
+        let Some(profile) = self
+            .transcribe
+            .profiles
+            .iter()
+            .find_map(|(key, profile)| key.eq_ignore_ascii_case(name).then_some(profile))
+        else {
+            let available: Vec<&str> = self
+                .transcribe
+                .profiles
+                .keys()
+                .map(String::as_str)
+                .collect();
+            if available.is_empty() {
+                bail!(
+                    "Unknown transcribe profile '{}': no profiles are configured under [transcribe.profiles]",
+                    name
+                );
+            }
+            bail!(
+                "Unknown transcribe profile '{}'. Available transcribe profiles: {}",
+                name,
+                available.join(", ")
+            );
+        };
+
+        let profile = profile.clone();
+        let transcribe = &mut self.transcribe;
+        if let Some(show_speaker_labels) = profile.show_speaker_labels {
+            transcribe.show_speaker_labels = show_speaker_labels;
+        }
+        if let Some(max_speaker_labels) = profile.max_speaker_labels {
+            transcribe.max_speaker_labels = max_speaker_labels;
+        }
+        if profile.vocabulary_name.is_some() {
+            transcribe.vocabulary_name = profile.vocabulary_name;
+        }
+        if profile.vocabulary_filter_name.is_some() {
+            transcribe.vocabulary_filter_name = profile.vocabulary_filter_name;
+        }
+        if let Some(vocabulary_filter_method) = profile.vocabulary_filter_method {
+            transcribe.vocabulary_filter_method = vocabulary_filter_method;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summarize::PromptRecord;
+    use crate::test_util::TempDir;
+
+    fn load(toml: &str) -> Settings {
+        let dir = TempDir::new();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, toml).unwrap();
+
+        Settings::load(&path).unwrap()
+    }
+
+    const PROFILES: &str = r#"
+        [model]
+        model_id = "anthropic.claude-3-sonnet-20240229-v1:0"
+        max_tokens = 2000
+        temperature = 1.0
+
+        [anthropic]
+        system = "You summarize meetings."
+
+        [prompt]
+        template = "Summarize: {transcript}"
+
+        [profiles.legal]
+        model_id = "anthropic.claude-3-opus-20240229-v1:0"
+        temperature = 0.2
+        system = "You summarize legal proceedings."
+    "#;
+
+    #[test]
+    fn with_profile_overrides_only_the_values_the_profile_sets() {
+        let settings = load(PROFILES);
+
+        let legal = settings.with_profile("legal").unwrap();
+
+        assert_eq!(
+            legal.model.model_id,
+            "anthropic.claude-3-opus-20240229-v1:0"
+        );
+        assert_eq!(legal.model.temperature, 0.2);
+        assert_eq!(legal.anthropic.system, "You summarize legal proceedings.");
+        // Values the profile leaves out keep their base settings
+        assert_eq!(legal.model.max_tokens, 2000);
+        assert_eq!(legal.prompt.template, "Summarize: {transcript}");
+        // The base settings are unchanged
+        assert_eq!(settings.model.temperature, 1.0);
+    }
+
+    #[test]
+    fn with_profile_matches_names_regardless_of_case() {
+        let legal = load(PROFILES).with_profile("Legal").unwrap();
+
+        assert_eq!(legal.model.temperature, 0.2);
+    }
+
+    #[test]
+    fn a_profile_template_is_recorded_as_coming_from_config_toml() {
+        let dir = TempDir::new();
+        std::fs::write(dir.join("standup.md"), "Summarize this standup.").unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[prompt]\ntemplate_file = \"standup.md\"\n\n\
+             [profiles.legal]\ntemplate = \"Summarize this hearing.\"\n",
+        )
+        .unwrap();
+        let settings = Settings::load(&path).unwrap();
+
+        let legal = settings.with_profile("legal").unwrap();
+
+        assert_eq!(legal.prompt.template, "Summarize this hearing.");
+        let record = PromptRecord::new(&legal, vec!["model".to_string()], &[]);
+        assert!(record.to_string().contains("Template file: config.toml\n"));
+        // The base settings still use the template file
+        assert_eq!(settings.prompt.template_path, Some(dir.join("standup.md")));
+    }
+
+    #[test]
+    fn with_profile_lists_the_available_profiles_for_an_unknown_name() {
+        let err = load(PROFILES).with_profile("standup").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile 'standup'. Available profiles: legal"
+        );
+    }
+
+    #[test]
+    fn template_file_is_read_relative_to_the_config_file_over_the_inline_template() {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.join("prompts")).unwrap();
+        std::fs::write(dir.join("prompts/standup.md"), "Summarize this standup.").unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[prompt]\ntemplate = \"Summarize.\"\ntemplate_file = \"prompts/standup.md\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(&path).unwrap();
+
+        assert_eq!(settings.prompt.template, "Summarize this standup.");
+        assert_eq!(
+            settings.prompt.template_path,
+            Some(dir.join("prompts/standup.md"))
+        );
+    }
+
+    #[test]
+    fn a_missing_template_file_is_reported_by_path() {
+        let dir = TempDir::new();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[prompt]\ntemplate_file = \"missing.md\"\n").unwrap();
+
+        let err = Settings::load(&path).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Unable to read prompt file {}",
+                dir.join("missing.md").display()
+            )
+        );
+    }
+
+    #[test]
+    fn load_prompt_file_replaces_the_template_and_an_empty_file_fails_validation() {
+        let dir = TempDir::new();
+        let prompt_file = dir.join("empty.md");
+        std::fs::write(&prompt_file, "\n").unwrap();
+        let mut settings = load(PROFILES);
+
+        settings.load_prompt_file(&prompt_file).unwrap();
+
+        assert_eq!(settings.prompt.template, "\n");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains(&format!(
+                "the prompt file {} is empty",
+                prompt_file.display()
+            )));
+    }
+
+    #[test]
+    fn notify_slack_requires_the_slack_webhook_endpoint() {
+        let mut settings = load(PROFILES);
+        settings.anthropic.anthropic_version = "bedrock-2023-05-31".to_string();
+        settings.notify.slack = true;
+
+        assert_eq!(
+            settings.validate().unwrap_err().to_string(),
+            "slack under [notify] requires webhook_endpoint under [slack]"
+        );
+
+        settings.slack.webhook_endpoint =
+            Some("https://hooks.slack.com/services/T000/B000/XXXX".to_string());
+        assert!(settings.validate().is_ok());
+    }
+}
@@ -1,80 +1,1098 @@
-use aws_config::SdkConfig;
+use aws_config::{Region, SdkConfig};
+use aws_sdk_bedrockruntime::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelError;
+use aws_sdk_bedrockruntime::types::ResponseStream;
 use aws_sdk_bedrockruntime::{primitives::Blob, Client};
 
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Context, Error};
 
-use config::{Config, File};
-use serde_json::json;
-use spinoff::Spinner;
-use std::str::from_utf8;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio::time::{sleep, sleep_until, timeout, timeout_at, Duration, Instant};
+use tracing::debug;
 
-pub async fn summarize_text(
-    config: &SdkConfig,
-    transcribed_text: &str,
-    spinner: &mut Spinner,
-) -> Result<String, Error> {
-    let client = Client::new(config);
-    let settings = Config::builder()
-        .add_source(File::with_name("config.toml"))
-        .build()?;
-
-    let prompt_template = settings.get_string("prompt.template").unwrap_or_default();
-
-    let prompt = format!("{prompt_template}\n\n{transcribed_text}");
-
-    // We're using the Anthropic Claude Messages API by default.
-    // If you switch models, you may need to update `messages`
-    // and/or `body`.
-    // https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters.html
-    // Claude: https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-anthropic-claude-messages.html
-    let messages = json!([
-        {
-            "role": "user",
-            "content": [
-                {
-                    "type": "text",
-                    "text": prompt,
-                }
-            ]
+use crate::settings::Settings;
+use crate::transcript::speaker_turns;
+
+/// The title of the action items section, whose items are listed with their
+/// owners.
+pub const ACTION_ITEMS: &str = "Action Items";
+
+// How long and in what form the summary should be. Without a style, the
+// prompt template alone decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryStyle {
+    // A short TL;DR
+    Brief,
+    // Thorough notes
+    Detailed,
+    // A bulleted list
+    Bullets,
+}
+
+impl SummaryStyle {
+    // The instruction appended to the prompt template
+    pub fn instruction(self) -> &'static str {
+        match self {
+            SummaryStyle::Brief => "Keep the summary brief: respond in at most 3 sentences.",
+            SummaryStyle::Detailed => "Write detailed notes: cover each topic discussed, the decisions made, and who said what, in as much depth as the transcript allows.",
+            SummaryStyle::Bullets => "Format the whole summary as a bulleted list of short points, without paragraphs.",
+        }
+    }
+}
+
+// A titled part of a structured summary, e.g. "Action Items".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummarySection {
+    pub title: String,
+    pub body: String,
+}
+
+// A Bedrock client for `config`, honoring bedrock.region and
+// bedrock.endpoint_url
+pub fn client(config: &SdkConfig, settings: &Settings) -> Client {
+    let mut builder = aws_sdk_bedrockruntime::config::Builder::from(config);
+    if let Some(region) = &settings.bedrock.region {
+        builder.set_region(Some(Region::new(region.clone())));
+    }
+    if let Some(endpoint_url) = &settings.bedrock.endpoint_url {
+        builder.set_endpoint_url(Some(endpoint_url.clone()));
+    }
+    let config = builder.build();
+    debug!(
+        "Amazon Bedrock region {}",
+        config.region().map_or("(none)", |region| region.as_ref())
+    );
+
+    Client::from_conf(config)
+}
+
+// Keeps Bedrock calls under account-level limits when several summaries run
+// at once: at most `max_concurrent` calls are in flight, and calls are spaced
+// to stay under `requests_per_minute`, if set.
+pub struct BedrockLimiter {
+    permits: Semaphore,
+    interval: Option<Duration>,
+    next_slot: Mutex<Instant>,
+}
+
+impl BedrockLimiter {
+    pub fn new(max_concurrent: usize, requests_per_minute: Option<u32>) -> Self {
+        Self {
+            permits: Semaphore::new(max_concurrent.max(1)),
+            interval: requests_per_minute
+                .filter(|rpm| *rpm > 0)
+                .map(|rpm| Duration::from_secs(60) / rpm),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Wait for a free slot. The call may proceed while the permit is held.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+
+        if let Some(interval) = self.interval {
+            let mut next_slot = self.next_slot.lock().await;
+            sleep_until(*next_slot).await;
+            *next_slot = Instant::now() + interval;
+        }
+
+        permit
+    }
+}
+
+// A summary along with the model that produced it and the token usage
+// reported by the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSummary {
+    pub model_id: String,
+    pub text: String,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+}
+
+// The Bedrock model families Distill can talk to. Each family has its own
+// request body and response format.
+// https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    // Claude, via the Messages API
+    Anthropic,
+    // Titan Text
+    Titan,
+    // Llama 3 and later
+    Llama,
+    // Command R and R+
+    Cohere,
+}
+
+impl ModelFamily {
+    // The family of a model id such as "anthropic.claude-3-haiku-20240307-v1:0",
+    // including cross-region inference profiles like "us.meta.llama3-...".
+    // Anything else, e.g. a provisioned throughput ARN, is assumed to be Claude.
+    pub fn from_model_id(model_id: &str) -> Self {
+        let provider = |name: &str| {
+            model_id.starts_with(&format!("{}.", name)) || model_id.contains(&format!(".{}.", name))
+        };
+
+        if provider("amazon") && model_id.contains("titan") {
+            ModelFamily::Titan
+        } else if provider("meta") {
+            ModelFamily::Llama
+        } else if provider("cohere") {
+            ModelFamily::Cohere
+        } else {
+            ModelFamily::Anthropic
+        }
+    }
+
+    // The request body for `prompt`, with the model parameters from `settings`
+    pub fn request_body(&self, settings: &Settings, prompt: &str, max_tokens: i64) -> Value {
+        let system = &settings.anthropic.system;
+        let model = &settings.model;
+
+        match self {
+            // https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-anthropic-claude-messages.html
+            ModelFamily::Anthropic => json!({
+                "anthropic_version": settings.anthropic.anthropic_version,
+                "max_tokens": max_tokens,
+                "system": system,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [{ "type": "text", "text": prompt }]
+                    }
+                ],
+                "temperature": model.temperature,
+                "top_p": model.top_p,
+                "top_k": model.top_k,
+            }),
+            // Titan has no system prompt, so it leads the prompt instead
+            // https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-titan-text.html
+            ModelFamily::Titan => json!({
+                "inputText": format!("{}\n\n{}", system, prompt),
+                "textGenerationConfig": {
+                    "maxTokenCount": max_tokens,
+                    "temperature": model.temperature,
+                    "topP": model.top_p,
+                },
+            }),
+            // https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-meta.html
+            ModelFamily::Llama => json!({
+                "prompt": format!(
+                    "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n{}<|eot_id|><|start_header_id|>user<|end_header_id|>\n\n{}<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n",
+                    system, prompt
+                ),
+                "max_gen_len": max_tokens,
+                "temperature": model.temperature,
+                "top_p": model.top_p,
+            }),
+            // https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-cohere-command-r-plus.html
+            ModelFamily::Cohere => json!({
+                "message": prompt,
+                "preamble": system,
+                "max_tokens": max_tokens,
+                "temperature": model.temperature,
+                "p": model.top_p,
+                "k": model.top_k,
+            }),
+        }
+    }
+
+    // The generated text in a response body
+    pub fn completion<'a>(&self, response: &'a Value) -> Option<&'a str> {
+        match self {
+            ModelFamily::Anthropic => response["content"][0]["text"].as_str(),
+            ModelFamily::Titan => response["results"][0]["outputText"].as_str(),
+            ModelFamily::Llama => response["generation"].as_str(),
+            ModelFamily::Cohere => response["text"].as_str(),
+        }
+    }
+
+    // The text in a chunk of a streamed response, if any
+    pub fn stream_delta<'a>(&self, chunk: &'a Value) -> Option<&'a str> {
+        match self {
+            ModelFamily::Anthropic => chunk["delta"]["text"].as_str(),
+            ModelFamily::Titan => chunk["outputText"].as_str(),
+            ModelFamily::Llama => chunk["generation"].as_str(),
+            ModelFamily::Cohere if chunk["event_type"] == "text-generation" => {
+                chunk["text"].as_str()
+            }
+            ModelFamily::Cohere => None,
+        }
+    }
+
+    // Token usage, where the response body reports it
+    pub fn input_tokens(&self, response: &Value) -> Option<i64> {
+        match self {
+            ModelFamily::Anthropic => response["usage"]["input_tokens"].as_i64(),
+            ModelFamily::Titan => response["inputTextTokenCount"].as_i64(),
+            ModelFamily::Llama => response["prompt_token_count"].as_i64(),
+            ModelFamily::Cohere => None,
+        }
+    }
+
+    pub fn output_tokens(&self, response: &Value) -> Option<i64> {
+        match self {
+            ModelFamily::Anthropic => response["usage"]["output_tokens"].as_i64(),
+            ModelFamily::Titan => response["results"][0]["tokenCount"].as_i64(),
+            ModelFamily::Llama => response["generation_token_count"].as_i64(),
+            ModelFamily::Cohere => None,
+        }
+    }
+}
+
+// Split a transcription into parts of at most `max_chars` characters, at
+// speaker turns where possible, so each part fits the model's context window.
+// A `max_chars` of 0 disables splitting.
+pub fn chunk_transcript(transcription: &str, max_chars: usize) -> Vec<String> {
+    if max_chars == 0 || transcription.chars().count() <= max_chars {
+        return vec![transcription.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+
+    for turn in speaker_turns(transcription) {
+        let line = match turn.speaker.as_str() {
+            "" => turn.text,
+            speaker => format!("{}: {}", speaker, turn.text),
+        };
+
+        for piece in split_long_line(&line, max_chars) {
+            let piece_len = piece.chars().count() + 1;
+            if chunk_len > 0 && chunk_len + piece_len > max_chars {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_len = 0;
+            }
+            chunk.push_str(&piece);
+            chunk.push('\n');
+            chunk_len += piece_len;
         }
-    ]);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+// Split a turn that is too long for one part at word boundaries
+fn split_long_line(line: &str, max_chars: usize) -> Vec<String> {
+    // Leave room for the line break
+    let max_chars = max_chars.saturating_sub(1).max(1);
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut piece_len = 0;
 
-    let body = json!(
-        {
-            "anthropic_version": settings.get_string("anthropic.anthropic_version").unwrap_or_default(),
-            "max_tokens": settings.get_int("model.max_tokens").unwrap_or_default(),
-            "system": settings.get_string("anthropic.system").unwrap_or_default(),
-            "messages": messages,
-            "temperature": settings.get_int("model.temperature").unwrap_or_default(),
-            "top_p": settings.get_int("model.top_p").unwrap_or_default(),
-            "top_k": settings.get_int("model.top_k").unwrap_or_default(),
+    for word in line.split_whitespace() {
+        let word_len = word.chars().count();
+        if piece_len > 0 && piece_len + 1 + word_len > max_chars {
+            pieces.push(std::mem::take(&mut piece));
+            piece_len = 0;
         }
+        if piece_len > 0 {
+            piece.push(' ');
+            piece_len += 1;
+        }
+        piece.push_str(word);
+        piece_len += word_len;
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+
+    pieces
+}
+
+// Summarize the transcription with a specific model, using the remaining
+// model parameters from `settings`.
+pub async fn summarize_with_model(
+    client: &Client,
+    settings: &Settings,
+    model_id: &str,
+    transcribed_text: &str,
+    sections: &[String],
+) -> Result<ModelSummary, Error> {
+    let prompt = summary_prompt(settings, sections, transcribed_text);
+    let summary = invoke(client, settings, model_id, &prompt).await?;
+
+    Ok(with_sections(summary, sections))
+}
+
+// Summarize one part of a transcript that is too long to summarize at once.
+// Parts are summarized free-form; sections are applied when they're combined.
+pub async fn summarize_part(
+    client: &Client,
+    settings: &Settings,
+    model_id: &str,
+    part: &str,
+    index: usize,
+    count: usize,
+) -> Result<ModelSummary, Error> {
+    let prompt = format!(
+        "{}\n\nThis is part {} of {} of the transcript.\n\n{}",
+        settings.prompt.template, index, count, part
+    );
+
+    invoke(client, settings, model_id, &prompt).await
+}
+
+// Combine the summaries of each part of a long transcript into one summary.
+// The token usage includes that of the parts.
+pub async fn combine_summaries(
+    client: &Client,
+    settings: &Settings,
+    model_id: &str,
+    parts: &[ModelSummary],
+    sections: &[String],
+) -> Result<ModelSummary, Error> {
+    let prompt = combined_prompt(settings, sections, parts);
+    let summary = invoke(client, settings, model_id, &prompt).await?;
+
+    Ok(with_part_usage(with_sections(summary, sections), parts))
+}
+
+// The prompt to summarize a whole transcript
+pub fn summary_prompt(settings: &Settings, sections: &[String], transcribed_text: &str) -> String {
+    let prompt_template = resolved_prompt(settings, sections);
+
+    format!("{prompt_template}\n\n{transcribed_text}")
+}
+
+// The prompt to combine the summaries of each part of a long transcript
+pub fn combined_prompt(settings: &Settings, sections: &[String], parts: &[ModelSummary]) -> String {
+    let summaries: Vec<String> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| format!("Part {}:\n{}", i + 1, part.text))
+        .collect();
+
+    format!(
+        "The transcript was too long to summarize at once, so each part of it has been summarized separately. Combine the summaries of the parts below into a single summary of the whole conversation.\n\n{}\n\n{}",
+        resolved_prompt(settings, sections),
+        summaries.join("\n\n")
     )
-    .to_string();
+}
 
-    let blob_body = Blob::new(body);
+// Render the model's output as the requested sections, if any
+fn with_sections(mut summary: ModelSummary, sections: &[String]) -> ModelSummary {
+    if !sections.is_empty() {
+        summary.text = render_sections(&parse_sections(&summary.text, sections));
+    }
 
-    spinner.update_text("Summarizing transcription...");
-    let response = client
-        .invoke_model()
-        .body(blob_body)
-        .content_type("application/json")
-        .accept("application/json")
-        .model_id(settings.get_string("model.model_id").unwrap_or_default())
-        .send()
-        .await;
+    summary
+}
+
+// Add the token usage of the parts a summary was combined from. The totals
+// are unknown if any part's usage is.
+pub fn with_part_usage(mut summary: ModelSummary, parts: &[ModelSummary]) -> ModelSummary {
+    summary.input_tokens = parts.iter().fold(summary.input_tokens, |total, part| {
+        Some(total? + part.input_tokens?)
+    });
+    summary.output_tokens = parts.iter().fold(summary.output_tokens, |total, part| {
+        Some(total? + part.output_tokens?)
+    });
+
+    summary
+}
+
+// Send `prompt` to the model and return its raw output
+async fn invoke(
+    client: &Client,
+    settings: &Settings,
+    model_id: &str,
+    prompt: &str,
+) -> Result<ModelSummary, Error> {
+    let family = ModelFamily::from_model_id(model_id);
+    let body = family
+        .request_body(settings, prompt, settings.model.max_tokens)
+        .to_string();
+
+    // Don't let a hung Bedrock call block the CLI forever. Throttling and
+    // transient service errors are retried with exponential backoff, up to
+    // bedrock.max_retries times. A timed out call is retried once if [retry]
+    // allows it, as a model that hangs twice is unlikely to answer a third time.
+    let timeout_secs = settings.bedrock.timeout_secs;
+    let max_retries = settings.bedrock.max_retries;
+    let mut retries = 0;
+    let mut retried_timeout = false;
+    let mut delay = Duration::from_secs(1);
+    debug!(
+        "Invoking model {} with a {} byte request ({} character prompt)",
+        model_id,
+        body.len(),
+        prompt.len()
+    );
+    let started = Instant::now();
+    let response = loop {
+        let request = client
+            .invoke_model()
+            .body(Blob::new(body.clone()))
+            .content_type("application/json")
+            .accept("application/json")
+            .model_id(model_id)
+            .send();
+
+        let reason = match timeout(Duration::from_secs(timeout_secs), request).await {
+            Ok(Err(err)) if retries < max_retries && is_retryable(&err) => format!(
+                "Amazon Bedrock returned {}",
+                err.code().unwrap_or("a transient error")
+            ),
+            Ok(response) => break response,
+            Err(_) if settings.retry.on_timeout && !retried_timeout => {
+                retried_timeout = true;
+                eprintln!(
+                    "\n⚠️  Amazon Bedrock did not respond within {} seconds, retrying once...",
+                    timeout_secs
+                );
+                continue;
+            }
+            Err(_) => bail!(
+                "Amazon Bedrock did not respond within {} seconds (model {}). Increase bedrock.timeout_secs in config.toml if summaries take longer.",
+                timeout_secs,
+                model_id
+            ),
+        };
+
+        retries += 1;
+        eprintln!(
+            "\n⚠️  {}, retrying in {}s ({}/{})...",
+            reason,
+            delay.as_secs(),
+            retries,
+            max_retries
+        );
+        sleep(delay).await;
+        delay *= 2;
+    };
 
     match response {
         Ok(output) => {
-            let response_body = from_utf8(output.body.as_ref()).unwrap_or("");
-            let response_json: serde_json::Value = serde_json::from_str(response_body).unwrap();
+            debug!(
+                "Model {} responded with {} bytes in {:.1?} ({} retries)",
+                model_id,
+                output.body.as_ref().len(),
+                started.elapsed(),
+                retries
+            );
+            let response_body = String::from_utf8_lossy(output.body.as_ref());
+            let response_json: Value = serde_json::from_str(&response_body).with_context(|| {
+                format!(
+                    "Expected a JSON response from model {}, but got:\n{}",
+                    model_id, response_body
+                )
+            })?;
 
-            let summarization = response_json["content"][0]["text"]
-                .as_str()
-                .unwrap()
+            let text = family
+                .completion(&response_json)
+                .with_context(|| {
+                    format!(
+                        "Expected a {:?} completion from model {}, but got:\n{}",
+                        family, model_id, response_body
+                    )
+                })?
                 .replace("\\n", "\n");
-            Ok(summarization.to_string())
+
+            Ok(ModelSummary {
+                model_id: model_id.to_string(),
+                text,
+                input_tokens: family.input_tokens(&response_json),
+                output_tokens: family.output_tokens(&response_json),
+            })
         }
         Err(e) => Err(anyhow!(e)),
     }
 }
+
+// Throttling and transient service-side errors are worth retrying; anything
+// else, e.g. a validation error, fails straight away.
+fn is_retryable(err: &SdkError<InvokeModelError>) -> bool {
+    match err.as_service_error() {
+        Some(err) => {
+            err.is_throttling_exception()
+                || err.is_service_unavailable_exception()
+                || err.is_internal_server_exception()
+                || err.is_model_not_ready_exception()
+        }
+        None => matches!(err, SdkError::TimeoutError(_)),
+    }
+}
+
+// Send `prompt` (see `summary_prompt` and `combined_prompt`) to the model and
+// write the summary to `out` as it is generated. With sections, `out` gets the
+// model's raw output and the returned summary is the rendered sections.
+pub async fn stream_with_model(
+    client: &Client,
+    settings: &Settings,
+    model_id: &str,
+    prompt: &str,
+    sections: &[String],
+    out: &mut impl Write,
+) -> Result<ModelSummary, Error> {
+    let family = ModelFamily::from_model_id(model_id);
+    let body = family
+        .request_body(settings, prompt, settings.model.max_tokens)
+        .to_string();
+
+    // The whole summary, not just the start of the stream, has to arrive
+    // within bedrock.timeout_secs
+    let timeout_secs = settings.bedrock.timeout_secs;
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    debug!(
+        "Streaming from model {} with a {} byte request ({} character prompt)",
+        model_id,
+        body.len(),
+        prompt.len()
+    );
+    let started = Instant::now();
+    let request = client
+        .invoke_model_with_response_stream()
+        .body(Blob::new(body))
+        .content_type("application/json")
+        .accept("application/json")
+        .model_id(model_id)
+        .send();
+    let mut output = match timeout_at(deadline, request).await {
+        Ok(output) => output.map_err(|e| anyhow!(e))?,
+        Err(_) => bail!(
+            "Amazon Bedrock did not respond within {} seconds (model {}). Increase bedrock.timeout_secs in config.toml if summaries take longer.",
+            timeout_secs,
+            model_id
+        ),
+    };
+
+    let mut summarization = String::new();
+    let mut input_tokens = None;
+    let mut output_tokens = None;
+    loop {
+        let Ok(event) = timeout_at(deadline, output.body.recv()).await else {
+            bail!(
+                "Amazon Bedrock did not finish the summary within {} seconds (model {}). Increase bedrock.timeout_secs in config.toml if summaries take longer.",
+                timeout_secs,
+                model_id
+            );
+        };
+        let Some(event) = event.map_err(|e| anyhow!(e))? else {
+            break;
+        };
+        let ResponseStream::Chunk(part) = event else {
+            continue;
+        };
+        let Some(bytes) = part.bytes() else {
+            continue;
+        };
+        let chunk: Value = serde_json::from_slice(bytes.as_ref()).with_context(|| {
+            format!(
+                "Expected a JSON chunk from model {}, but got:\n{}",
+                model_id,
+                String::from_utf8_lossy(bytes.as_ref())
+            )
+        })?;
+
+        if let Some(text) = family.stream_delta(&chunk) {
+            write!(out, "{}", text)?;
+            out.flush()?;
+            summarization.push_str(text);
+        }
+
+        // Bedrock reports token usage in the last chunk, for every model
+        let metrics = &chunk["amazon-bedrock-invocationMetrics"];
+        input_tokens = metrics["inputTokenCount"].as_i64().or(input_tokens);
+        output_tokens = metrics["outputTokenCount"].as_i64().or(output_tokens);
+    }
+    writeln!(out)?;
+    debug!(
+        "Model {} streamed {} characters in {:.1?}",
+        model_id,
+        summarization.len(),
+        started.elapsed()
+    );
+
+    let summary = ModelSummary {
+        model_id: model_id.to_string(),
+        text: summarization,
+        input_tokens,
+        output_tokens,
+    };
+
+    Ok(with_sections(summary, sections))
+}
+
+// The exact prompt sent to the model, for auditing which prompt produced a
+// summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptRecord {
+    pub model_ids: Vec<String>,
+    pub system: String,
+    // The prompt template with any section instructions; the transcription
+    // is appended to it
+    pub prompt: String,
+    pub template_path: Option<PathBuf>,
+    pub template_sha256: String,
+}
+
+impl PromptRecord {
+    pub fn new(settings: &Settings, model_ids: Vec<String>, sections: &[String]) -> Self {
+        Self {
+            model_ids,
+            system: settings.anthropic.system.clone(),
+            prompt: resolved_prompt(settings, sections),
+            template_path: settings.prompt.template_path.clone(),
+            template_sha256: format!("{:x}", Sha256::digest(settings.prompt.template.as_bytes())),
+        }
+    }
+}
+
+impl fmt::Display for PromptRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Model: {}", self.model_ids.join(", "))?;
+        match &self.template_path {
+            Some(path) => writeln!(f, "Template file: {}", path.display())?,
+            None => writeln!(f, "Template file: config.toml")?,
+        }
+        writeln!(f, "Template SHA-256: {}", self.template_sha256)?;
+        writeln!(f)?;
+        writeln!(f, "System:\n{}", self.system)?;
+        writeln!(f)?;
+        write!(
+            f,
+            "Prompt (followed by the transcription):\n{}",
+            self.prompt
+        )
+    }
+}
+
+// The prompt template, with instructions for the summary style and any
+// requested sections.
+pub fn resolved_prompt(settings: &Settings, sections: &[String]) -> String {
+    let template = match settings.prompt.style {
+        Some(style) => format!(
+            "{}\n\n{}",
+            settings.prompt.template.trim_end(),
+            style.instruction()
+        ),
+        None => settings.prompt.template.clone(),
+    };
+
+    if sections.is_empty() {
+        template
+    } else {
+        structured_prompt(&template, sections)
+    }
+}
+
+// Check that the configured model can be invoked, using a one-token request.
+pub async fn check_model_access(client: &Client, settings: &Settings) -> Result<(), Error> {
+    let body = ModelFamily::from_model_id(&settings.model.model_id)
+        .request_body(settings, "Hello", 1)
+        .to_string();
+
+    client
+        .invoke_model()
+        .body(Blob::new(body))
+        .content_type("application/json")
+        .accept("application/json")
+        .model_id(&settings.model.model_id)
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+// Extend the prompt template with instructions to produce each section. An
+// action items section also asks for each item's owner.
+pub fn structured_prompt(template: &str, sections: &[String]) -> String {
+    let headings: Vec<String> = sections
+        .iter()
+        .map(|title| format!("## {}", title))
+        .collect();
+
+    let mut prompt = format!(
+        "{}\n\nStructure your summary into exactly the following sections, in this order. \
+         Start each section with its heading on a line of its own, exactly as written below. \
+         If there is nothing to report for a section, write \"None.\" under its heading.\n\n{}\n",
+        template.trim_end(),
+        headings.join("\n")
+    );
+    if let Some(title) = sections
+        .iter()
+        .find(|title| title.eq_ignore_ascii_case(ACTION_ITEMS))
+    {
+        prompt.push_str(&format!(
+            "\nUnder \"{}\", list each action item as a bullet point, naming its owner where \
+             the transcript makes it clear.\n",
+            title
+        ));
+    }
+
+    prompt
+}
+
+// Split the model's response into the requested sections. Headings are
+// matched case-insensitively; sections that are missing or empty get "None."
+pub fn parse_sections(summary: &str, sections: &[String]) -> Vec<SummarySection> {
+    let mut bodies: Vec<Vec<&str>> = vec![Vec::new(); sections.len()];
+    let mut current: Option<usize> = None;
+
+    for line in summary.lines() {
+        if let Some(title) = section_title(line) {
+            if let Some(index) = sections
+                .iter()
+                .position(|section| section.eq_ignore_ascii_case(title))
+            {
+                current = Some(index);
+                continue;
+            }
+        }
+        if let Some(index) = current {
+            bodies[index].push(line);
+        }
+    }
+
+    sections
+        .iter()
+        .zip(bodies)
+        .map(|(title, lines)| {
+            let body = lines.join("\n").trim().to_string();
+            SummarySection {
+                title: title.clone(),
+                body: if body.is_empty() {
+                    "None.".to_string()
+                } else {
+                    body
+                },
+            }
+        })
+        .collect()
+}
+
+pub fn render_sections(sections: &[SummarySection]) -> String {
+    sections
+        .iter()
+        .map(|section| format!("## {}\n\n{}", section.title, section.body))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// The title of a "## Title" section heading line.
+pub fn section_title(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("## ")
+        .map(|title| title.trim().trim_end_matches(':').trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{claude_response, MockHttp, MockResponse, TempDir};
+    use aws_smithy_eventstream::frame::write_message_to;
+    use aws_smithy_types::event_stream::{Header, HeaderValue, Message};
+    use base64::Engine;
+
+    fn sections(titles: &[&str]) -> Vec<String> {
+        titles.iter().map(|title| title.to_string()).collect()
+    }
+
+    #[test]
+    fn summary_prompt_asks_for_each_section_in_order() {
+        let mut settings = Settings::default();
+        settings.prompt.template = "Summarize this meeting.".to_string();
+
+        let prompt = summary_prompt(
+            &settings,
+            &sections(&["Overview", "Action Items"]),
+            "spk_0: Hello",
+        );
+
+        assert!(prompt.starts_with("Summarize this meeting.\n\nStructure your summary"));
+        assert!(prompt.contains("\n\n## Overview\n## Action Items\n"));
+        assert!(prompt.ends_with("\n\nspk_0: Hello"));
+    }
+
+    #[test]
+    fn summary_prompt_asks_for_action_item_owners() {
+        let mut settings = Settings::default();
+        settings.prompt.template = "Summarize this meeting.".to_string();
+
+        let prompt = summary_prompt(
+            &settings,
+            &sections(&["Summary", "Action Items"]),
+            "spk_0: Hello",
+        );
+
+        // The template is kept as it is, with the instruction after the headings
+        assert!(prompt.starts_with("Summarize this meeting.\n\nStructure your summary"));
+        assert!(prompt.contains(
+            "## Action Items\n\nUnder \"Action Items\", list each action item as a bullet point, \
+             naming its owner"
+        ));
+        assert_eq!(settings.prompt.template, "Summarize this meeting.");
+        assert!(!summary_prompt(&settings, &sections(&["Summary"]), "").contains("owner"));
+    }
+
+    #[test]
+    fn summary_prompt_without_sections_is_the_template() {
+        let mut settings = Settings::default();
+        settings.prompt.template = "Summarize this meeting.".to_string();
+
+        assert_eq!(
+            summary_prompt(&settings, &[], "spk_0: Hello"),
+            "Summarize this meeting.\n\nspk_0: Hello"
+        );
+    }
+
+    #[test]
+    fn parse_sections_reads_back_the_requested_sections() {
+        let summary = "Preamble the model added.\n\n## overview:\nA weekly sync.\n\n## Risks\n\n## Action Items\n- Ship it\n- Tell QA\n";
+
+        let parsed = parse_sections(
+            summary,
+            &sections(&["Overview", "Action Items", "Risks", "Decisions"]),
+        );
+
+        let bodies: Vec<(&str, &str)> = parsed
+            .iter()
+            .map(|section| (section.title.as_str(), section.body.as_str()))
+            .collect();
+        assert_eq!(
+            bodies,
+            vec![
+                ("Overview", "A weekly sync."),
+                ("Action Items", "- Ship it\n- Tell QA"),
+                ("Risks", "None."),
+                ("Decisions", "None."),
+            ]
+        );
+        assert_eq!(
+            render_sections(&parsed[..2]),
+            "## Overview\n\nA weekly sync.\n\n## Action Items\n\n- Ship it\n- Tell QA"
+        );
+    }
+
+    const MODEL_ID: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+
+    // Settings with a one second Bedrock timeout, retrying timeouts if
+    // `on_timeout`
+    fn timeout_settings(on_timeout: bool) -> Settings {
+        let mut settings = Settings::default();
+        settings.bedrock.timeout_secs = 1;
+        settings.retry.on_timeout = on_timeout;
+        settings
+    }
+
+    // A mock that takes `delays[i]` to answer the i-th call, and then answers
+    // at once
+    fn slow_bedrock(delays: Vec<u64>) -> MockHttp {
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        MockHttp::new(move |_| {
+            let call = calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let delay = delays.get(call).copied().unwrap_or_default();
+            claude_response("Shipped.").delayed(Duration::from_secs(delay))
+        })
+    }
+
+    // A Bedrock response stream event carrying a model chunk
+    fn chunk_event(chunk: Value) -> Vec<u8> {
+        let payload = json!({
+            "bytes": base64::engine::general_purpose::STANDARD.encode(chunk.to_string()),
+        });
+        let header = |name: &'static str, value: &'static str| {
+            Header::new(name, HeaderValue::String(value.into()))
+        };
+        let message = Message::new(payload.to_string())
+            .add_header(header(":message-type", "event"))
+            .add_header(header(":event-type", "chunk"))
+            .add_header(header(":content-type", "application/json"));
+        let mut event = Vec::new();
+        write_message_to(&message, &mut event).unwrap();
+        event
+    }
+
+    fn text_delta(text: &str) -> Value {
+        json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": text}})
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timed_out_call_is_retried() {
+        let mock = slow_bedrock(vec![5]);
+        let settings = timeout_settings(true);
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let summary = invoke(&client, &settings, MODEL_ID, "Summarize")
+            .await
+            .unwrap();
+
+        assert_eq!(summary.text, "Shipped.");
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timed_out_call_is_only_retried_once() {
+        let mock = slow_bedrock(vec![5, 5, 5, 5]);
+        let settings = timeout_settings(true);
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let err = invoke(&client, &settings, MODEL_ID, "Summarize")
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("did not respond within 1 seconds"));
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timed_out_call_is_not_retried_without_on_timeout() {
+        let mock = slow_bedrock(vec![5]);
+        let settings = timeout_settings(false);
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let err = invoke(&client, &settings, MODEL_ID, "Summarize")
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("did not respond within 1 seconds"));
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_with_model_writes_the_summary_as_it_arrives() {
+        let mut events = chunk_event(text_delta("Ship "));
+        events.extend(chunk_event(text_delta("it.")));
+        let mock = MockHttp::new(move |_| MockResponse::bytes(events.clone()));
+        let settings = timeout_settings(false);
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let mut out = Vec::new();
+        let summary = stream_with_model(&client, &settings, MODEL_ID, "Summarize", &[], &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.text, "Ship it.");
+        assert_eq!(String::from_utf8(out).unwrap(), "Ship it.\n");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_with_model_times_out_a_stream_that_stalls() {
+        let first = chunk_event(text_delta("Ship "));
+        let rest = chunk_event(text_delta("it."));
+        let mock = MockHttp::new(move |_| {
+            MockResponse::bytes(first.clone()).then_after(Duration::from_secs(5), rest.clone())
+        });
+        let settings = timeout_settings(false);
+        let client = client(&mock.sdk_config().await, &settings);
+
+        let mut out = Vec::new();
+        let err = stream_with_model(&client, &settings, MODEL_ID, "Summarize", &[], &mut out)
+            .await
+            .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("did not finish the summary within 1 seconds"));
+        assert_eq!(String::from_utf8(out).unwrap(), "Ship ");
+    }
+
+    #[tokio::test]
+    async fn client_honors_the_bedrock_endpoint_and_region() {
+        let mock = MockHttp::new(|_| claude_response("Hi"));
+        let mut settings = Settings::default();
+        settings.model.model_id = MODEL_ID.to_string();
+        settings.bedrock.endpoint_url =
+            Some("https://vpce-1.bedrock-runtime.example.com".to_string());
+        settings.bedrock.region = Some("eu-west-1".to_string());
+        let client = client(&mock.sdk_config().await, &settings);
+
+        check_model_access(&client, &settings).await.unwrap();
+
+        assert_eq!(client.config().region().unwrap().as_ref(), "eu-west-1");
+        assert!(mock.requests()[0]
+            .uri
+            .starts_with("https://vpce-1.bedrock-runtime.example.com/model/"));
+    }
+
+    #[tokio::test]
+    async fn the_recorded_prompt_is_the_prompt_sent_to_the_model() {
+        let dir = TempDir::new();
+        let prompt_file = dir.join("standup.prompt.md");
+        std::fs::write(&prompt_file, "Summarize this standup.").unwrap();
+        let mut settings = Settings::default();
+        settings.load_prompt_file(&prompt_file).unwrap();
+        settings.anthropic.system = "You are Distiller.".to_string();
+        settings.prompt.style = Some(SummaryStyle::Brief);
+        let sections = sections(&["Blockers"]);
+        let mock = MockHttp::new(|_| claude_response("## Blockers\n\nNone."));
+        let client = client(&mock.sdk_config().await, &settings);
+
+        summarize_with_model(&client, &settings, MODEL_ID, "spk_0: Hi", &sections)
+            .await
+            .unwrap();
+
+        let record = PromptRecord::new(&settings, vec![MODEL_ID.to_string()], &sections);
+        let sent = mock.requests()[0].json();
+        assert_eq!(sent["system"], record.system.as_str());
+        assert_eq!(
+            sent["messages"][0]["content"][0]["text"],
+            format!("{}\n\nspk_0: Hi", record.prompt)
+        );
+        assert!(record.prompt.contains(SummaryStyle::Brief.instruction()));
+        assert!(record.prompt.contains("## Blockers"));
+        assert_eq!(record.template_path, Some(prompt_file));
+        assert_eq!(
+            record.template_sha256,
+            format!("{:x}", Sha256::digest("Summarize this standup."))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bedrock_limiter_caps_the_calls_in_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let limiter = Arc::new(BedrockLimiter::new(2, None));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let calls: Vec<_> = (0..5)
+            .map(|_| {
+                let (limiter, in_flight, most_in_flight) =
+                    (limiter.clone(), in_flight.clone(), most_in_flight.clone());
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire().await;
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_flight.fetch_max(now, Ordering::SeqCst);
+                    sleep(Duration::from_secs(1)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for call in calls {
+            call.await.unwrap();
+        }
+
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bedrock_limiter_spaces_calls_to_stay_under_the_rate() {
+        let limiter = BedrockLimiter::new(4, Some(30));
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            drop(limiter.acquire().await);
+        }
+
+        // 30 calls a minute is one every 2 seconds
+        assert_eq!(started.elapsed(), Duration::from_secs(4));
+    }
+}
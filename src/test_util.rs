@@ -0,0 +1,352 @@
+// Test helpers: an HTTP client that answers AWS SDK calls from a closure
+// instead of the network, so code that takes SDK clients can run against
+// canned responses, and a local server for plain HTTP downloads. Also included
+// by the integration tests, so it only uses external crates.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_sdk_s3::config::Credentials;
+use aws_smithy_runtime_api::client::http::{
+    HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+use aws_smithy_types::byte_stream::ByteStream;
+use bytes::Bytes;
+use http_body::{Body, Frame};
+use serde_json::json;
+use tokio::time::Sleep;
+use uuid::Uuid;
+
+// A request the mock received
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub uri: String,
+    // The x-amz-target header of JSON protocol calls, e.g.
+    // "Transcribe.GetTranscriptionJob"
+    pub target: Option<String>,
+    pub body: String,
+}
+
+impl MockRequest {
+    // The operation name of a JSON protocol call, e.g. "GetTranscriptionJob"
+    pub fn operation(&self) -> &str {
+        self.target
+            .as_deref()
+            .and_then(|target| target.rsplit('.').next())
+            .unwrap_or_default()
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+// The mock's answer to a request: a status, a body and optional delays before
+// it arrives and before the rest of the body does
+pub struct MockResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub delay: Option<Duration>,
+    pub rest: Option<(Duration, Vec<u8>)>,
+}
+
+impl MockResponse {
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self::status(200, body)
+    }
+
+    pub fn status(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: body.into().into_bytes(),
+            delay: None,
+            rest: None,
+        }
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    // Send `rest` after the body, `delay` later, e.g. to stall a stream
+    pub fn then_after(mut self, delay: Duration, rest: impl Into<Vec<u8>>) -> Self {
+        self.rest = Some((delay, rest.into()));
+        self
+    }
+
+    // A response with a binary `body`, e.g. event stream messages
+    pub fn bytes(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            body: body.into(),
+            ..Self::ok("")
+        }
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+// An SDK HTTP client that records every request and answers it with the
+// handler's response
+#[derive(Clone)]
+pub struct MockHttp {
+    handler: Arc<Handler>,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockHttp {
+    pub fn new(handler: impl Fn(&MockRequest) -> MockResponse + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            requests: Arc::default(),
+        }
+    }
+
+    // The handler's response to `request`, e.g. to fall back on from another
+    // mock's handler
+    pub fn respond(&self, request: &MockRequest) -> MockResponse {
+        (self.handler)(request)
+    }
+
+    // The requests received so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // An SDK config with test credentials that sends everything to this mock
+    pub async fn sdk_config(&self) -> SdkConfig {
+        aws_config::defaults(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("akid", "secret", None, None, "test"))
+            .http_client(self.clone())
+            .load()
+            .await
+    }
+}
+
+impl fmt::Debug for MockHttp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockHttp").finish_non_exhaustive()
+    }
+}
+
+impl HttpConnector for MockHttp {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let mock = self.clone();
+        HttpConnectorFuture::new(async move {
+            let method = request.method().to_string();
+            let uri = request.uri().to_string();
+            let target = request.headers().get("x-amz-target").map(str::to_string);
+            // Read the whole body, as a server would, so streaming uploads run
+            let body = ByteStream::new(request.into_body())
+                .collect()
+                .await
+                .map(|data| String::from_utf8_lossy(&data.into_bytes()).into_owned())
+                .unwrap_or_default();
+
+            let request = MockRequest {
+                method,
+                uri,
+                target,
+                body,
+            };
+            let response = (mock.handler)(&request);
+            mock.requests.lock().unwrap().push(request);
+
+            if let Some(delay) = response.delay {
+                tokio::time::sleep(delay).await;
+            }
+            let status = StatusCode::try_from(response.status).expect("a valid status code");
+            let body = match response.rest {
+                Some((delay, rest)) => SdkBody::from_body_1_x(SlowBody {
+                    first: Some(Bytes::from(response.body)),
+                    rest: Some(Bytes::from(rest)),
+                    delay: Box::pin(tokio::time::sleep(delay)),
+                }),
+                None => SdkBody::from(response.body),
+            };
+            Ok(HttpResponse::new(status, body))
+        })
+    }
+}
+
+// A response body that sends `first` at once and `rest` once `delay` is up
+struct SlowBody {
+    first: Option<Bytes>,
+    rest: Option<Bytes>,
+    delay: Pin<Box<Sleep>>,
+}
+
+impl Body for SlowBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(first) = self.first.take().filter(|first| !first.is_empty()) {
+            return Poll::Ready(Some(Ok(Frame::data(first))));
+        }
+        if self.rest.is_some() && self.delay.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        Poll::Ready(self.rest.take().map(|rest| Ok(Frame::data(rest))))
+    }
+}
+
+impl HttpClient for MockHttp {
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+// Serve `responses` (status and body) over plain HTTP on localhost, one per
+// request in order and the last one from then on, e.g. a transcript file for
+// reqwest to download. Returns the server's base URL.
+pub fn serve(responses: Vec<(u16, String)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        let mut responses = responses.into_iter().peekable();
+        for mut stream in listener.incoming().flatten() {
+            // Read up to the end of the headers; GET requests have no body
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let (status, body) = match responses.next() {
+                Some(response) if responses.peek().is_some() => response,
+                Some(response) => {
+                    responses = vec![response.clone()].into_iter().peekable();
+                    response
+                }
+                None => (404, String::new()),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+
+    url
+}
+
+// A uniquely named temporary directory, removed with its contents when
+// dropped
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("distill-test-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+
+        Self(path)
+    }
+
+    // The path of `name` in the directory
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Amazon Transcribe JSON for `words`, each with its speaker label, e.g.
+// ("spk_0", "Hello"), spoken half a second apart
+pub fn transcript_json(words: &[(&str, &str)]) -> String {
+    let items: Vec<serde_json::Value> = words
+        .iter()
+        .enumerate()
+        .map(|(i, (speaker, word))| {
+            json!({
+                "type": "pronunciation",
+                "start_time": format!("{:.1}", i as f64 * 0.5),
+                "end_time": format!("{:.1}", i as f64 * 0.5 + 0.4),
+                "speaker_label": speaker,
+                "alternatives": [{"confidence": "0.99", "content": word}],
+            })
+        })
+        .collect();
+
+    json!({ "results": { "items": items } }).to_string()
+}
+
+// Answers the calls a run makes: the bucket's region (us-west-2) and uploads
+// (S3), a job that completes at once with `transcript` (Transcribe), and
+// `summary` from Claude (Bedrock)
+pub fn mock_aws(transcript: &str, summary: &str) -> MockHttp {
+    let transcript_url = format!("{}/transcript.json", serve(vec![(200, transcript.into())]));
+    let summary = summary.to_string();
+
+    MockHttp::new(move |request| {
+        if request.uri.contains("?location") {
+            return MockResponse::ok(
+                r#"<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/">us-west-2</LocationConstraint>"#,
+            );
+        }
+        if request.uri.contains("/model/") {
+            return claude_response(&summary);
+        }
+
+        match request.operation() {
+            "StartTranscriptionJob" | "GetTranscriptionJob" => MockResponse::ok(
+                json!({
+                    "TranscriptionJob": {
+                        "TranscriptionJobName": request.json()["TranscriptionJobName"],
+                        "TranscriptionJobStatus": "COMPLETED",
+                        "LanguageCode": "en-US",
+                        "Transcript": {"TranscriptFileUri": transcript_url},
+                    }
+                })
+                .to_string(),
+            ),
+            // Uploads and deleting the finished job
+            _ => MockResponse::ok("{}"),
+        }
+    })
+}
+
+// A Claude response from Bedrock's InvokeModel with `text`
+pub fn claude_response(text: &str) -> MockResponse {
+    MockResponse::ok(
+        json!({
+            "content": [{"type": "text", "text": text}],
+            "usage": {"input_tokens": 120, "output_tokens": 8}
+        })
+        .to_string(),
+    )
+}
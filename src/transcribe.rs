@@ -1,72 +1,852 @@
 use aws_config::SdkConfig;
+use aws_sdk_transcribe::error::ProvideErrorMetadata;
+use aws_sdk_transcribe::operation::get_transcription_job::GetTranscriptionJobOutput;
 use aws_sdk_transcribe::types::{
-    LanguageCode, Media, MediaFormat, Settings, TranscriptionJobStatus,
+    ContentRedaction, LanguageCode, Media, MediaFormat, PiiEntityType, RedactionOutput,
+    RedactionType, Settings, Tag, TranscriptionJobStatus, VocabularyFilterMethod,
 };
 use aws_sdk_transcribe::Client;
 
 use anyhow::{anyhow, bail, Context, Error};
 use infer::get_from_path;
 use serde_json::Value;
-use spinoff::{spinners, Spinner};
+use std::io::Read;
 use std::path::Path;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::debug;
 use uuid::Uuid;
 
+use crate::progress::Progress;
+use crate::settings::{Settings as DistillSettings, TranscribeSettings};
+use crate::transcript::{speaker_count, SpeakerTurn, TimedWord, TranscriptSegment};
+use crate::{output, InFlight, Options};
+
+// The result of a transcription job
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Transcription {
+    pub job_name: String,
+    // The job's language, e.g. the one Transcribe identified with "auto"
+    pub language_code: Option<String>,
+    pub turns: Vec<SpeakerTurn>,
+    // With --keep-alternatives, the alternative transcriptions of each
+    // segment
+    #[serde(default)]
+    pub alternatives: Vec<TranscriptSegment>,
+}
+
+// Media formats accepted by Amazon Transcribe, for overriding detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMediaFormat {
+    Amr,
+    Flac,
+    M4a,
+    Mp3,
+    Mp4,
+    Ogg,
+    Wav,
+    Webm,
+}
+
+impl From<InputMediaFormat> for MediaFormat {
+    fn from(format: InputMediaFormat) -> Self {
+        match format {
+            InputMediaFormat::Amr => MediaFormat::Amr,
+            InputMediaFormat::Flac => MediaFormat::Flac,
+            InputMediaFormat::M4a => MediaFormat::M4A,
+            InputMediaFormat::Mp3 => MediaFormat::Mp3,
+            InputMediaFormat::Mp4 => MediaFormat::Mp4,
+            InputMediaFormat::Ogg => MediaFormat::Ogg,
+            InputMediaFormat::Wav => MediaFormat::Wav,
+            InputMediaFormat::Webm => MediaFormat::Webm,
+        }
+    }
+}
+
+// What a vocabulary filter does with the words in it: replace them with
+// "***", leave them out, or keep them but flag them in the raw transcript
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum FilterMethod {
+    #[default]
+    Mask,
+    Remove,
+    Tag,
+}
+
+impl TryFrom<String> for FilterMethod {
+    type Error = String;
+
+    fn try_from(method: String) -> Result<Self, Self::Error> {
+        match method.to_lowercase().as_str() {
+            "mask" => Ok(FilterMethod::Mask),
+            "remove" => Ok(FilterMethod::Remove),
+            "tag" => Ok(FilterMethod::Tag),
+            _ => Err(format!(
+                "invalid vocabulary_filter_method '{}': expected \"mask\", \"remove\" or \"tag\"",
+                method
+            )),
+        }
+    }
+}
+
+impl From<FilterMethod> for VocabularyFilterMethod {
+    fn from(method: FilterMethod) -> Self {
+        match method {
+            FilterMethod::Mask => VocabularyFilterMethod::Mask,
+            FilterMethod::Remove => VocabularyFilterMethod::Remove,
+            FilterMethod::Tag => VocabularyFilterMethod::Tag,
+        }
+    }
+}
+
+// A Transcribe client for `config`, honoring transcribe.endpoint_url
+pub fn client(config: &SdkConfig, settings: &DistillSettings) -> Client {
+    let mut builder = aws_sdk_transcribe::config::Builder::from(config);
+    if let Some(endpoint_url) = &settings.transcribe.endpoint_url {
+        builder.set_endpoint_url(Some(endpoint_url.clone()));
+    }
+
+    Client::from_conf(builder.build())
+}
+
 pub async fn transcribe_audio(
-    config: &SdkConfig,
-    file_path: &Path,
+    client: &Client,
+    media_format: &MediaFormat,
     s3_uri: &str,
-    spinner: &mut Spinner,
-    language_code: &str,
-) -> Result<String, Error> {
-    let client = Client::new(config);
+    spinner: &mut Progress,
+    opts: &Options,
+    settings: &DistillSettings,
+    in_flight: &InFlight,
+) -> Result<Transcription, Error> {
+    spinner.update("Submitting transcription job");
 
-    spinner.update(spinners::Dots7, "Submitting transcription job", None);
-    let job_name = format!("transcription-{}", Uuid::new_v4()); // Generate a unique job name
-    let media = Media::builder().media_file_uri(s3_uri).build();
+    // "auto" lets Transcribe identify the language
+    let language = match opts.language_code.as_str() {
+        "auto" => {
+            let language_options = opts
+                .language_options
+                .iter()
+                .map(|code| parse_language_code(code))
+                .collect::<Result<Vec<_>, _>>()?;
+            if language_options.len() == 1 {
+                bail!("\nLanguage identification needs at least two language options");
+            }
+            JobLanguage::Identify(language_options)
+        }
+        code => JobLanguage::Code(parse_language_code(code)?),
+    };
+    let fallback_language = opts
+        .fallback_language
+        .as_deref()
+        .map(parse_language_code)
+        .transpose()?;
+    if let Some(min_confidence) = opts.min_language_confidence {
+        if !(0.0..=1.0).contains(&min_confidence) {
+            bail!(
+                "\nInvalid minimum language confidence {}: must be between 0 and 1",
+                min_confidence
+            );
+        }
+    }
 
-    let media_format = match get_from_path(file_path) {
-        Ok(Some(kind)) => match kind.mime_type() {
-            "audio/amr" => MediaFormat::Amr,
-            "audio/flac" => MediaFormat::Flac,
-            "audio/m4a" => MediaFormat::M4A,
-            "audio/mpeg" => MediaFormat::Mp3,
-            "audio/mp4" => MediaFormat::Mp4,
-            "video/mp4" => MediaFormat::Mp4,
-            "audio/ogg" => MediaFormat::Ogg,
-            "audio/opus" => MediaFormat::Ogg,
-            "audio/wav" => MediaFormat::Wav,
-            "audio/webm" => MediaFormat::Webm,
-            _ => {
-                // Fallback to checking the file extension (MP3s sometimes cause issues)
-                match file_path.extension().and_then(|ext| ext.to_str()) {
-                    Some("mp3") => MediaFormat::Mp3,
-                    _ => {
-                        bail!("\nUnsupported media format: {}", kind.mime_type());
-                    }
+    if let Some(speakers) = opts.speakers_expected {
+        if !(MIN_SPEAKERS..=MAX_SPEAKERS).contains(&speakers) {
+            bail!(
+                "\nInvalid number of expected speakers {}: Amazon Transcribe supports between {} and {}",
+                speakers,
+                MIN_SPEAKERS,
+                MAX_SPEAKERS
+            );
+        }
+    }
+    let transcribe_settings = &settings.transcribe;
+    if transcribe_settings.show_speaker_labels {
+        if !(MIN_SPEAKERS..=MAX_SPEAKERS).contains(&transcribe_settings.max_speaker_labels) {
+            bail!(
+                "\nInvalid max_speaker_labels {}: Amazon Transcribe supports between {} and {}",
+                transcribe_settings.max_speaker_labels,
+                MIN_SPEAKERS,
+                MAX_SPEAKERS
+            );
+        }
+    } else if opts.speakers_expected.is_some() {
+        bail!("\n--speakers-expected needs speaker labels, which the transcription settings turn off (show_speaker_labels = false)");
+    }
+    if let Some(alternatives) = opts.keep_alternatives {
+        if !(MIN_ALTERNATIVES..=MAX_ALTERNATIVES).contains(&alternatives) {
+            bail!(
+                "\nInvalid number of alternatives {}: Amazon Transcribe supports between {} and {}",
+                alternatives,
+                MIN_ALTERNATIVES,
+                MAX_ALTERNATIVES
+            );
+        }
+    }
+
+    let mut job_name =
+        start_transcription_job(client, s3_uri, media_format, &language, opts, settings).await?;
+    // A kept job isn't deleted, even when the run is interrupted
+    if !opts.keep_job {
+        in_flight.add_job(client, &job_name);
+    }
+    let mut job_details = match wait_for_job(client, &job_name, &settings.transcribe, spinner).await
+    {
+        Ok(job_details) => job_details,
+        Err(err) => {
+            finish_job(client, &job_name, opts, in_flight).await;
+            return Err(err);
+        }
+    };
+
+    if matches!(language, JobLanguage::Identify(_)) && job_completed(&job_details) {
+        let job = job_details.transcription_job.as_ref();
+        let detected_language = job
+            .and_then(|j| j.language_code())
+            .map(|code| code.as_str().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let confidence = job.and_then(|j| j.identified_language_score());
+
+        spinner.note("");
+        match confidence {
+            Some(confidence) => spinner.note(&format!(
+                "🌐 Detected language: {} (confidence {:.2})",
+                detected_language, confidence
+            )),
+            None => spinner.note(&format!("🌐 Detected language: {}", detected_language)),
+        }
+
+        match language_decision(
+            confidence,
+            opts.min_language_confidence,
+            fallback_language.is_some(),
+        ) {
+            LanguageDecision::Accept => {}
+            LanguageDecision::Warn => {
+                spinner.warn(&format!(
+                    "Language identification confidence is below {}. The transcript may be in the wrong language; use --fallback-language to re-run with an explicit language.",
+                    opts.min_language_confidence.unwrap_or_default()
+                ));
+            }
+            LanguageDecision::Fallback => {
+                let fallback_language = fallback_language.unwrap();
+                spinner.warn(&format!(
+                    "Language identification confidence is below {}. Re-running transcription as {}.",
+                    opts.min_language_confidence.unwrap_or_default(),
+                    fallback_language.as_str()
+                ));
+                finish_job(client, &job_name, opts, in_flight).await;
+                job_name = start_transcription_job(
+                    client,
+                    s3_uri,
+                    media_format,
+                    &JobLanguage::Code(fallback_language),
+                    opts,
+                    settings,
+                )
+                .await?;
+                if !opts.keep_job {
+                    in_flight.add_job(client, &job_name);
                 }
+                job_details =
+                    match wait_for_job(client, &job_name, &settings.transcribe, spinner).await {
+                        Ok(job_details) => job_details,
+                        Err(err) => {
+                            finish_job(client, &job_name, opts, in_flight).await;
+                            return Err(err);
+                        }
+                    };
             }
-        },
-        Ok(None) => {
-            // Fallback to checking the file extension
-            match file_path.extension().and_then(|ext| ext.to_str()) {
-                Some("mp3") => MediaFormat::Mp3,
-                _ => {
-                    bail!("\nUnable to determine media format from file extension");
+        }
+    }
+
+    let transcript = job_transcript(&job_details, opts, spinner).await;
+    finish_job(client, &job_name, opts, in_flight).await;
+    let (turns, alternatives) = transcript?;
+    let language_code = job_details
+        .transcription_job
+        .as_ref()
+        .and_then(|j| j.language_code())
+        .map(|code| code.as_str().to_string());
+
+    Ok(Transcription {
+        job_name,
+        language_code,
+        turns,
+        alternatives,
+    })
+}
+
+// The speaker turns of a finished job's transcript, and with
+// --keep-alternatives the alternatives of each segment
+async fn job_transcript(
+    job_details: &GetTranscriptionJobOutput,
+    opts: &Options,
+    spinner: &mut Progress,
+) -> Result<(Vec<SpeakerTurn>, Vec<TranscriptSegment>), Error> {
+    let job = job_details.transcription_job.as_ref();
+
+    match job.and_then(|j| j.transcription_job_status.as_ref()) {
+        Some(TranscriptionJobStatus::Completed) => {
+            if let Some(transcript_uri) = job
+                .and_then(|j| j.transcript.as_ref())
+                .and_then(|t| t.redacted_transcript_file_uri().or(t.transcript_file_uri()))
+            {
+                spinner.update("Transcription job complete");
+                let body = fetch_transcript(transcript_uri).await?;
+                if opts.save_raw_transcript {
+                    let path = output::write_raw_transcript(opts.output_file.as_deref(), &body)?;
+                    spinner.note("");
+                    spinner.note(&format!("💾 Raw transcript written to {}", path.display()));
                 }
+                let turns = transcribe_json_turns(&body)?;
+                let alternatives = match opts.keep_alternatives {
+                    Some(max) => transcribe_json_alternatives(&body, max as usize)?,
+                    None => Vec::new(),
+                };
+
+                if let Some(warning) = speaker_count_warning(&turns, opts.speakers_expected) {
+                    spinner.warn(&warning);
+                }
+
+                Ok((turns, alternatives))
+            } else {
+                bail!("\nThe transcription job completed without a transcript file URI");
             }
         }
-        Err(err) => {
-            bail!("\nError determining media format: {}", err);
+        Some(TranscriptionJobStatus::Failed) => match job.and_then(|j| j.failure_reason()) {
+            Some(reason) => bail!("\nTranscription job failed: {}", reason),
+            None => bail!("\nTranscription job failed for an unknown reason"),
+        },
+        Some(status) => bail!(
+            "\nTranscription job ended with an unexpected status: {}",
+            status.as_str()
+        ),
+        None => bail!("\nThe transcription job status could not be determined"),
+    }
+}
+
+// A warning when Transcribe told apart a different number of speakers than
+// --speakers-expected asked for, as the speaker labels are then likely off
+fn speaker_count_warning(turns: &[SpeakerTurn], expected: Option<i32>) -> Option<String> {
+    let expected = expected?;
+    let detected = speaker_count(turns);
+
+    (detected != expected as usize).then(|| {
+        format!(
+            "Expected {} speakers but Amazon Transcribe detected {}. Speaker labels may be inaccurate.",
+            expected, detected
+        )
+    })
+}
+
+// Delete a job that is no longer needed, whether it succeeded or not, unless
+// --keep-job asks to keep it
+async fn finish_job(client: &Client, job_name: &str, opts: &Options, in_flight: &InFlight) {
+    if !opts.keep_job {
+        delete_job(client, job_name).await;
+        in_flight.remove_job(job_name);
+    }
+}
+
+// Delete a finished job so jobs don't pile up against the account's quota.
+// Failing to do so doesn't fail the run.
+pub async fn delete_job(client: &Client, job_name: &str) {
+    if let Err(err) = client
+        .delete_transcription_job()
+        .transcription_job_name(job_name)
+        .send()
+        .await
+    {
+        eprintln!(
+            "\n⚠️  Unable to delete transcription job {}: {}",
+            job_name, err
+        );
+    }
+}
+
+// How many times to try downloading the transcript of a completed job
+const TRANSCRIPT_FETCH_ATTEMPTS: u32 = 4;
+
+// Download the transcript of a completed job. The job has already been paid
+// for, so transient failures are retried with backoff, and on final failure
+// the URI is reported so the transcript can be retrieved manually.
+async fn fetch_transcript(transcript_uri: &str) -> Result<String, Error> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+
+    let started = Instant::now();
+    loop {
+        let result = async {
+            reqwest::get(transcript_uri)
+                .await?
+                .error_for_status()?
+                .text()
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(body) => {
+                debug!(
+                    "Downloaded the transcript ({} bytes) in {:.1?}",
+                    body.len(),
+                    started.elapsed()
+                );
+                return Ok(body);
+            }
+            Err(err) if attempt < TRANSCRIPT_FETCH_ATTEMPTS => {
+                eprintln!(
+                    "\n⚠️  Failed to download the transcript ({}), retrying in {}s...",
+                    err,
+                    delay.as_secs()
+                );
+                sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(anyhow!(err).context(format!(
+                    "\nFailed to download the transcript after {} attempts. It can be retrieved manually (for a limited time) from:\n{}",
+                    TRANSCRIPT_FETCH_ATTEMPTS, transcript_uri
+                )));
+            }
         }
+    }
+}
+
+// What to do with an automatically identified language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageDecision {
+    // Confident enough, or no threshold was requested
+    Accept,
+    // Below the threshold and no fallback language is configured
+    Warn,
+    // Below the threshold; re-run with the fallback language
+    Fallback,
+}
+
+pub fn language_decision(
+    confidence: Option<f32>,
+    min_confidence: Option<f32>,
+    has_fallback: bool,
+) -> LanguageDecision {
+    let Some(min_confidence) = min_confidence else {
+        return LanguageDecision::Accept;
     };
 
-    let settings = Settings::builder()
-        .show_speaker_labels(true)
-        .max_speaker_labels(10)
+    // A missing score is treated as no confidence at all
+    if confidence.unwrap_or(0.0) >= min_confidence {
+        LanguageDecision::Accept
+    } else if has_fallback {
+        LanguageDecision::Fallback
+    } else {
+        LanguageDecision::Warn
+    }
+}
+
+// Job names look like "transcription-<id>", or "<prefix>-transcription-<id>"
+// with a prefix, so that teams can find their jobs in the Transcribe console.
+pub fn job_name(prefix: Option<&str>, id: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}-transcription-{}", prefix, id),
+        None => format!("transcription-{}", id),
+    }
+}
+
+// Transcribe job names may only contain letters, digits, '.', '_' and '-',
+// and are limited to 200 characters. Leave room for the rest of the name.
+pub fn validate_job_prefix(prefix: &str) -> Result<(), Error> {
+    const MAX_PREFIX_LEN: usize = 200 - "-transcription-".len() - 36;
+
+    if prefix.is_empty() {
+        bail!("\nThe job prefix must not be empty.");
+    }
+    if prefix.len() > MAX_PREFIX_LEN {
+        bail!(
+            "\nThe job prefix must be at most {} characters long.",
+            MAX_PREFIX_LEN
+        );
+    }
+    if let Some(c) = prefix
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+    {
+        bail!(
+            "\nInvalid character '{}' in job prefix {}. Only letters, digits, '.', '_' and '-' are allowed.",
+            c,
+            prefix
+        );
+    }
+
+    Ok(())
+}
+
+fn job_completed(job_details: &GetTranscriptionJobOutput) -> bool {
+    matches!(
+        job_details
+            .transcription_job
+            .as_ref()
+            .and_then(|j| j.transcription_job_status.as_ref()),
+        Some(TranscriptionJobStatus::Completed)
+    )
+}
+
+// The range of speaker labels Transcribe supports
+const MIN_SPEAKERS: i32 = 2;
+const MAX_SPEAKERS: i32 = 30;
+
+// The number of alternatives per segment Transcribe supports
+const MIN_ALTERNATIVES: i32 = 2;
+const MAX_ALTERNATIVES: i32 = 10;
+
+// The language of a transcription job
+#[derive(Debug, Clone)]
+enum JobLanguage {
+    Code(LanguageCode),
+    // Let Transcribe identify the language from these candidates, or from
+    // every supported language when empty
+    Identify(Vec<LanguageCode>),
+}
+
+// Start a transcription job, returning its name
+async fn start_transcription_job(
+    client: &Client,
+    s3_uri: &str,
+    media_format: &MediaFormat,
+    language: &JobLanguage,
+    opts: &Options,
+    settings: &DistillSettings,
+) -> Result<String, Error> {
+    let job_name = job_name(opts.job_prefix.as_deref(), &Uuid::new_v4().to_string()); // Generate a unique job name
+    let media = Media::builder().media_file_uri(s3_uri).build();
+
+    let job_settings = Settings::builder()
+        .show_speaker_labels(settings.transcribe.show_speaker_labels)
+        // Transcribe rejects a maximum without speaker labels
+        .set_max_speaker_labels(settings.transcribe.show_speaker_labels.then(|| {
+            opts.speakers_expected
+                .unwrap_or(settings.transcribe.max_speaker_labels)
+        }))
+        .set_vocabulary_name(settings.transcribe.vocabulary_name.clone())
+        .set_vocabulary_filter_name(settings.transcribe.vocabulary_filter_name.clone())
+        .set_vocabulary_filter_method(
+            settings
+                .transcribe
+                .vocabulary_filter_name
+                .as_ref()
+                .map(|_| settings.transcribe.vocabulary_filter_method.into()),
+        )
+        .set_show_alternatives(opts.keep_alternatives.map(|_| true))
+        .set_max_alternatives(opts.keep_alternatives)
         .channel_identification(false)
         .build();
 
+    // aws.tags and --tag, e.g. for cost allocation
+    let tags = settings
+        .aws
+        .tags
+        .iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let request = client
+        .start_transcription_job()
+        .transcription_job_name(&job_name)
+        .media_format(media_format.clone())
+        .media(media)
+        .settings(job_settings)
+        .set_tags((!tags.is_empty()).then_some(tags));
+
+    // Redacted entities come back as "[PII]" in the transcript
+    let request = if opts.redact_pii {
+        let entity_types = settings
+            .transcribe
+            .pii_entity_types
+            .iter()
+            .map(|entity_type| PiiEntityType::from(entity_type.to_uppercase().as_str()))
+            .collect::<Vec<_>>();
+        let content_redaction = ContentRedaction::builder()
+            .redaction_type(RedactionType::Pii)
+            .redaction_output(RedactionOutput::Redacted)
+            .set_pii_entity_types((!entity_types.is_empty()).then_some(entity_types))
+            .build()?;
+        request.content_redaction(content_redaction)
+    } else {
+        request
+    };
+
+    let request = match language {
+        JobLanguage::Code(language_code) => request.language_code(language_code.clone()),
+        JobLanguage::Identify(options) if options.is_empty() => request.identify_language(true),
+        JobLanguage::Identify(options) => request
+            .identify_language(true)
+            .set_language_options(Some(options.clone())),
+    };
+
+    if let Err(err) = request.send().await {
+        // A missing or unusable vocabulary or vocabulary filter is reported
+        // as a bad request
+        let vocabularies = [
+            (
+                &settings.transcribe.vocabulary_name,
+                "custom vocabulary",
+                "transcribe.vocabulary_name",
+            ),
+            (
+                &settings.transcribe.vocabulary_filter_name,
+                "vocabulary filter",
+                "transcribe.vocabulary_filter_name",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, kind, key)| {
+            name.as_ref()
+                .map(|name| format!("the {} '{}' ({})", kind, name, key))
+        })
+        .collect::<Vec<_>>();
+
+        return match err.as_service_error() {
+            Some(service_err)
+                if service_err.is_bad_request_exception() && !vocabularies.is_empty() =>
+            {
+                let reason = service_err.message().unwrap_or_default().to_string();
+                Err(anyhow!(err).context(format!(
+                    "\nAmazon Transcribe rejected the job: {}\nCheck that {} {} ready in the region of your S3 bucket.",
+                    reason,
+                    vocabularies.join(" and "),
+                    if vocabularies.len() > 1 { "exist and are" } else { "exists and is" }
+                )))
+            }
+            _ => Err(anyhow!(err).context("\nFailed to start the transcription job")),
+        };
+    }
+
+    debug!(
+        "Started transcription job {} for {} ({})",
+        job_name,
+        s3_uri,
+        media_format.as_str()
+    );
+    if !opts.quiet {
+        println!();
+        println!("📝 Started transcription job {}", job_name);
+    }
+
+    Ok(job_name)
+}
+
+// Poll a transcription job until it is no longer in progress
+async fn wait_for_job(
+    client: &Client,
+    job_name: &str,
+    settings: &TranscribeSettings,
+    spinner: &mut Progress,
+) -> Result<GetTranscriptionJobOutput, Error> {
+    spinner.update("Waiting for transcription to complete...");
+    let started = Instant::now();
+    let mut polls = 1;
+    let mut poll_interval = Duration::from_secs(5);
+    let max_poll_interval = Duration::from_secs(settings.poll_max_seconds.max(1));
+    // Give up eventually, so a job that never finishes can't keep the CLI
+    // polling forever
+    let deadline = started + Duration::from_secs(settings.timeout_secs);
+    let mut job_details = client
+        .get_transcription_job()
+        .transcription_job_name(job_name)
+        .send()
+        .await?;
+
+    while let Some(status) = job_details
+        .transcription_job
+        .as_ref()
+        .and_then(|j| j.transcription_job_status.as_ref())
+    {
+        match status {
+            TranscriptionJobStatus::Queued | TranscriptionJobStatus::InProgress => {
+                if Instant::now() >= deadline {
+                    bail!(
+                        "\nTranscription job {} did not finish within {} seconds (transcribe.timeout_secs)",
+                        job_name,
+                        settings.timeout_secs
+                    );
+                }
+                if polls >= settings.max_polls {
+                    bail!(
+                        "\nTranscription job {} did not finish within {} status checks (transcribe.max_polls)",
+                        job_name,
+                        settings.max_polls
+                    );
+                }
+                // Transcribe doesn't report a percentage, so keep the elapsed
+                // time ticking between polls to show the job isn't stuck
+                let activity = match status {
+                    TranscriptionJobStatus::Queued => "Queued",
+                    _ => "Transcribing",
+                };
+                let next_poll = Instant::now() + poll_interval;
+                while Instant::now() < next_poll {
+                    spinner.update_text(progress_message(activity, started.elapsed(), polls));
+                    sleep(
+                        next_poll
+                            .saturating_duration_since(Instant::now())
+                            .min(Duration::from_secs(1)),
+                    )
+                    .await;
+                }
+                job_details = client
+                    .get_transcription_job()
+                    .transcription_job_name(job_name)
+                    .send()
+                    .await?;
+                polls += 1;
+                // Exponential backoff, capped so the end of a long job isn't missed
+                poll_interval = (poll_interval * 2).min(max_poll_interval);
+            }
+            TranscriptionJobStatus::Completed | TranscriptionJobStatus::Failed => {
+                debug!(
+                    "Transcription job {} {} after {:.1?} ({} polls)",
+                    job_name,
+                    status.as_str(),
+                    started.elapsed(),
+                    polls
+                );
+                break;
+            }
+            _ => {
+                bail!(
+                    "\nTranscription job {} ended with an unexpected status: {}",
+                    job_name,
+                    status.as_str()
+                );
+            }
+        }
+    }
+
+    Ok(job_details)
+}
+
+// E.g. "Transcribing… 02:30 elapsed (poll 8)"
+fn progress_message(activity: &str, elapsed: Duration, polls: u32) -> String {
+    let seconds = elapsed.as_secs();
+    format!(
+        "{}… {:02}:{:02} elapsed (poll {})",
+        activity,
+        seconds / 60,
+        seconds % 60,
+        polls
+    )
+}
+
+// Language codes accepted by --language-code, with the language name, in
+// the order Amazon Transcribe documents them. Keep in sync with
+// `parse_language_code`.
+pub const SUPPORTED_LANGUAGES: &[(&str, &str)] = &[
+    ("ab-GE", "Abkhaz"),
+    ("af-ZA", "Afrikaans"),
+    ("ar-AE", "Arabic, Gulf"),
+    ("ar-SA", "Arabic, Modern Standard"),
+    ("hy-AM", "Armenian"),
+    ("ast-ES", "Asturian"),
+    ("az-AZ", "Azerbaijani"),
+    ("ba-RU", "Bashkir"),
+    ("eu-ES", "Basque"),
+    ("be-BY", "Belarusian"),
+    ("bn-IN", "Bengali"),
+    ("bs-BA", "Bosnian"),
+    ("bg-BG", "Bulgarian"),
+    ("ca-ES", "Catalan"),
+    ("ckb-IR", "Central Kurdish, Iran"),
+    ("ckb-IQ", "Central Kurdish, Iraq"),
+    ("zh-CN", "Chinese, Simplified"),
+    ("zh-TW", "Chinese, Traditional"),
+    ("hr-HR", "Croatian"),
+    ("cs-CZ", "Czech"),
+    ("da-DK", "Danish"),
+    ("nl-NL", "Dutch"),
+    ("en-AU", "English, Australian"),
+    ("en-GB", "English, British"),
+    ("en-IN", "English, Indian"),
+    ("en-IE", "English, Irish"),
+    ("en-NZ", "English, New Zealand"),
+    ("en-AB", "English, Scottish"),
+    ("en-ZA", "English, South African"),
+    ("en-US", "English, US"),
+    ("en-WL", "English, Welsh"),
+    ("et-ET", "Estonian"),
+    ("fa-IR", "Farsi"),
+    ("fi-FI", "Finnish"),
+    ("fr-FR", "French"),
+    ("fr-CA", "French, Canadian"),
+    ("gl-ES", "Galician"),
+    ("ka-GE", "Georgian"),
+    ("de-DE", "German"),
+    ("de-CH", "German, Swiss"),
+    ("el-GR", "Greek"),
+    ("gu-IN", "Gujarati"),
+    ("ha-NG", "Hausa"),
+    ("he-IL", "Hebrew"),
+    ("hi-IN", "Hindi"),
+    ("hu-HU", "Hungarian"),
+    ("is-IS", "Icelandic"),
+    ("id-ID", "Indonesian"),
+    ("it-IT", "Italian"),
+    ("ja-JP", "Japanese"),
+    ("kab-DZ", "Kabyle"),
+    ("kn-IN", "Kannada"),
+    ("kk-KZ", "Kazakh"),
+    ("rw-RW", "Kinyarwanda"),
+    ("ko-KR", "Korean"),
+    ("ky-KG", "Kyrgyz"),
+    ("lv-LV", "Latvian"),
+    ("lt-LT", "Lithuanian"),
+    ("lg-IN", "Luganda"),
+    ("mk-MK", "Macedonian"),
+    ("ms-MY", "Malay"),
+    ("ml-IN", "Malayalam"),
+    ("mt-MT", "Maltese"),
+    ("mr-IN", "Marathi"),
+    ("mhr-RU", "Meadow Mari"),
+    ("mn-MN", "Mongolian"),
+    ("no-NO", "Norwegian Bokmål"),
+    ("or-IN", "Odia"),
+    ("ps-AF", "Pashto"),
+    ("pl-PL", "Polish"),
+    ("pt-PT", "Portuguese"),
+    ("pt-BR", "Portuguese, Brazilian"),
+    ("pa-IN", "Punjabi"),
+    ("ro-RO", "Romanian"),
+    ("ru-RU", "Russian"),
+    ("sr-RS", "Serbian"),
+    ("si-LK", "Sinhala"),
+    ("sk-SK", "Slovak"),
+    ("sl-SI", "Slovenian"),
+    ("so-SO", "Somali"),
+    ("es-ES", "Spanish"),
+    ("es-US", "Spanish, US"),
+    ("su-ID", "Sundanese"),
+    ("sw-KE", "Swahili, Kenya"),
+    ("sw-BI", "Swahili, Burundi"),
+    ("sw-RW", "Swahili, Rwanda"),
+    ("sw-TZ", "Swahili, Tanzania"),
+    ("sw-UG", "Swahili, Uganda"),
+    ("sv-SE", "Swedish"),
+    ("tl-PH", "Tagalog/Filipino"),
+    ("ta-IN", "Tamil"),
+    ("tt-RU", "Tatar"),
+    ("te-IN", "Telugu"),
+    ("th-TH", "Thai"),
+    ("tr-TR", "Turkish"),
+    ("uk-UA", "Ukrainian"),
+    ("ug-CN", "Uyghur"),
+    ("uz-UZ", "Uzbek"),
+    ("vi-VN", "Vietnamese"),
+    ("cy-WL", "Welsh"),
+    ("wo-SN", "Wolof"),
+    ("zu-ZA", "Zulu"),
+];
+
+pub fn parse_language_code(language_code: &str) -> Result<LanguageCode, Error> {
     let language_code_enum = match language_code {
         "ab-GE" => LanguageCode::AbGe,
         "af-ZA" => LanguageCode::AfZa,
@@ -173,146 +953,912 @@ pub async fn transcribe_audio(
 
         // Add other language codes as needed
         _ => {
-            bail!("\nUnsupported language code: {}", language_code);
+            bail!(
+                "\nUnsupported language code: {}. Run `distill-cli languages` to list the supported codes.",
+                language_code
+            );
         }
     };
 
-    let _job = client
-        .start_transcription_job()
-        .transcription_job_name(&job_name)
-        .language_code(language_code_enum)
-        .media_format(media_format)
-        .media(media)
-        .settings(settings)
-        .send()
-        .await?;
+    Ok(language_code_enum)
+}
 
-    println!();
-    spinner.update(
-        spinners::Dots7,
-        "Waiting for transcription to complete...",
-        None,
-    );
-    let mut poll_interval = Duration::from_secs(5);
-    let mut job_details = client
-        .get_transcription_job()
-        .transcription_job_name(&job_name)
-        .send()
-        .await?;
+// Fail fast on inputs that would only waste a transcription job: empty files,
+// and (unless the format was overridden) files with no recognizable media
+// container, which are usually truncated or corrupt. Returns the media format
+// to transcribe the file as. Files over Transcribe's limits are only warned
+// about, by limits_warning.
+pub fn check_input_file(
+    file_path: &Path,
+    media_format: Option<InputMediaFormat>,
+    default_media_format: Option<InputMediaFormat>,
+) -> Result<MediaFormat, Error> {
+    let metadata = std::fs::metadata(file_path)
+        .with_context(|| format!("Unable to read {}", file_path.display()))?;
 
-    while let Some(status) = job_details
-        .transcription_job
-        .as_ref()
-        .and_then(|j| j.transcription_job_status.as_ref())
-    {
-        match status {
-            TranscriptionJobStatus::InProgress => {
-                sleep(poll_interval).await;
-                job_details = client
-                    .get_transcription_job()
-                    .transcription_job_name(&job_name)
-                    .send()
-                    .await?;
-                println!();
-                poll_interval *= 2; // Exponential backoff to show progress
-            }
-            TranscriptionJobStatus::Completed => {
-                break;
-            }
-            _ => {
-                // ToDo Handle other states, e.g., Failed
-                break;
+    if metadata.len() == 0 {
+        bail!("\nThe file {} is empty.", file_path.display());
+    }
+
+    // An explicit format skips sniffing entirely
+    let media_format = match media_format {
+        Some(format) => format.into(),
+        None => {
+            // A known extension isn't enough, as junk and truncated files
+            // usually keep theirs
+            if !matches!(get_from_path(file_path), Ok(Some(_))) {
+                bail!(
+                    "\nThe file {} is not a recognizable audio or video file; it may be corrupt or truncated. Use --media-format to override detection.",
+                    file_path.display()
+                );
             }
+            detect_media_format(file_path, default_media_format)?
         }
+    };
+
+    Ok(media_format)
+}
+
+// Amazon Transcribe rejects batch jobs over 2 GB or 4 hours of audio
+const MAX_FILE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+const MAX_DURATION: Duration = Duration::from_secs(4 * 60 * 60);
+// The highest MP3 bitrate, in bits per second, so an MP3 is at least
+// size / MAX_MP3_BITRATE long
+const MAX_MP3_BITRATE: u64 = 320_000;
+
+// A warning, before uploading, about a file Transcribe will likely reject for
+// its size or length. The length is only known for WAV files, and bounded for
+// MP3s; anything else is left to Transcribe. The upload goes ahead either way,
+// as the estimates can be off.
+pub fn limits_warning(file_path: &Path, media_format: &MediaFormat) -> Option<String> {
+    let size = std::fs::metadata(file_path).ok()?.len();
+    if size > MAX_FILE_SIZE {
+        return Some(format!(
+            "The file {} is {:.1} GB; Amazon Transcribe accepts files up to 2 GB. {}",
+            file_path.display(),
+            size as f64 / (1024.0 * 1024.0 * 1024.0),
+            split_hint(file_path)
+        ));
     }
 
-    match job_details
-        .transcription_job
-        .as_ref()
-        .and_then(|j| j.transcription_job_status.as_ref())
-    {
-        Some(TranscriptionJobStatus::Completed) => {
-            if let Some(transcript_uri) = job_details
-                .transcription_job
-                .and_then(|j| j.transcript)
-                .and_then(|t| t.transcript_file_uri)
-            {
-                spinner.update(spinners::Dots7, "Transcription job complete", None);
-                let res = reqwest::get(transcript_uri).await?;
-                let body = res.text().await?;
-                let final_transcript = convert_transcribe_json(&body)?;
-                Ok(final_transcript)
-            } else {
-                println!("Transcript file URI is missing.");
-                Ok("Transcript file URI is missing.".to_string())
+    let duration = match media_format {
+        MediaFormat::Wav => wav_duration(file_path),
+        MediaFormat::Mp3 => Some(Duration::from_secs(size * 8 / MAX_MP3_BITRATE)),
+        _ => None,
+    };
+    duration
+        .filter(|duration| *duration > MAX_DURATION)
+        .map(|duration| {
+            format!(
+                "The file {} is at least {:.1} hours long; Amazon Transcribe accepts up to 4 hours of audio. {}",
+                file_path.display(),
+                duration.as_secs_f64() / 3600.0,
+                split_hint(file_path)
+            )
+        })
+}
+
+// E.g. "Split it into parts with: ffmpeg -i talk.mp3 -f segment -segment_time
+// 3:00:00 -c copy talk-%03d.mp3"
+fn split_hint(file_path: &Path) -> String {
+    let stem = file_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let extension = file_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    format!(
+        "Split it into parts with: ffmpeg -i {} -f segment -segment_time 3:00:00 -c copy {}-%03d{}",
+        file_path.display(),
+        stem,
+        extension
+    )
+}
+
+// The length of a WAV file from its header: the size of the "data" chunk
+// over the byte rate in the "fmt " chunk. None if the header can't be read.
+fn wav_duration(file_path: &Path) -> Option<Duration> {
+    let mut header = Vec::new();
+    std::fs::File::open(file_path)
+        .ok()?
+        .take(64 * 1024)
+        .read_to_end(&mut header)
+        .ok()?;
+    if header.get(0..4)? != b"RIFF" || header.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let u32_at = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            header.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while offset + 8 <= header.len() {
+        let id = &header[offset..offset + 4];
+        let size = u32_at(offset + 4)?;
+        match id {
+            b"fmt " => byte_rate = u32_at(offset + 16),
+            b"data" => {
+                let byte_rate = byte_rate.filter(|rate| *rate > 0)?;
+                return Some(Duration::from_secs_f64(size as f64 / byte_rate as f64));
             }
+            _ => {}
         }
-        Some(TranscriptionJobStatus::Failed) => {
-            if let Some(reason) = job_details.transcription_job.and_then(|j| j.failure_reason) {
-                println!("Transcription job failed: {}", reason);
-            } else {
-                println!("Transcription job failed for an unknown reason.");
+        // Chunks are padded to an even size
+        offset += 8 + size as usize + (size as usize % 2);
+    }
+
+    None
+}
+
+// Detect the media format from the file contents, falling back to the file
+// extension and then to `default_media_format`, if configured.
+pub fn detect_media_format(
+    file_path: &Path,
+    default_media_format: Option<InputMediaFormat>,
+) -> Result<MediaFormat, Error> {
+    let media_format = match get_from_path(file_path) {
+        Ok(Some(kind)) => match kind.mime_type() {
+            "audio/amr" => MediaFormat::Amr,
+            "audio/flac" | "audio/x-flac" => MediaFormat::Flac,
+            "audio/m4a" | "audio/x-m4a" => MediaFormat::M4A,
+            "audio/mpeg" => MediaFormat::Mp3,
+            "audio/mp4" | "video/mp4" | "video/x-m4v" => MediaFormat::Mp4,
+            "audio/ogg" | "audio/opus" => MediaFormat::Ogg,
+            "audio/wav" | "audio/x-wav" => MediaFormat::Wav,
+            "audio/webm" | "video/webm" => MediaFormat::Webm,
+            mime_type => {
+                // Fallback to checking the file extension (MP3s sometimes cause issues)
+                match (format_from_extension(file_path), default_media_format) {
+                    (Some(format), _) => format.into(),
+                    (None, Some(format)) => assumed_media_format(file_path, format),
+                    (None, None) => bail!(
+                        "\nUnsupported media format: {}. {}",
+                        mime_type,
+                        convert_hint(file_path)
+                    ),
+                }
+            }
+        },
+        Ok(None) => {
+            // Fallback to checking the file extension
+            match (format_from_extension(file_path), default_media_format) {
+                (Some(format), _) => format.into(),
+                (None, Some(format)) => assumed_media_format(file_path, format),
+                (None, None) => {
+                    bail!(
+                        "\nUnable to determine media format from file extension. {}",
+                        convert_hint(file_path)
+                    );
+                }
             }
-            Ok("Transcription job failed.".to_string())
         }
-        _ => Ok(
-            "Job ended with an unexpected status or status could not be determined.".to_string(),
+        Err(err) => {
+            bail!("\nError determining media format: {}", err);
+        }
+    };
+
+    Ok(media_format)
+}
+
+// transcribe.default_media_format, with a warning that the format is a guess
+fn assumed_media_format(file_path: &Path, format: InputMediaFormat) -> MediaFormat {
+    eprintln!(
+        "\n⚠️  Unable to determine the media format of {}; assuming {:?} (transcribe.default_media_format)",
+        file_path.display(),
+        format
+    );
+    format.into()
+}
+
+// The media format named by a file's extension, e.g. "m4a", including common
+// alternative extensions for the same format
+fn format_from_extension(path: &Path) -> Option<InputMediaFormat> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "oga" | "opus" => Some(InputMediaFormat::Ogg),
+        "m4v" => Some(InputMediaFormat::Mp4),
+        "wave" => Some(InputMediaFormat::Wav),
+        extension => clap::ValueEnum::from_str(extension, true).ok(),
+    }
+}
+
+// How to convert a file Amazon Transcribe can't read, e.g. a .mov, .aac or
+// .wma recording
+fn convert_hint(path: &Path) -> String {
+    format!(
+        "Amazon Transcribe supports AMR, FLAC, M4A, MP3, MP4, Ogg, WAV and WebM; convert the file first, e.g. `ffmpeg -i {} {}`",
+        path.display(),
+        path.with_extension("m4a").display()
+    )
+}
+
+// The media format of an object already in S3, which can't be sniffed
+// locally, so it's taken from the key's extension
+pub fn media_format_from_key(
+    key: &str,
+    media_format: Option<InputMediaFormat>,
+    default_media_format: Option<InputMediaFormat>,
+) -> Result<MediaFormat, Error> {
+    if let Some(format) = media_format {
+        return Ok(format.into());
+    }
+
+    match format_from_extension(Path::new(key)).or(default_media_format) {
+        Some(format) => Ok(format.into()),
+        None => bail!(
+            "\nUnable to determine the media format of S3 object {}",
+            key
         ),
     }
 }
 
-pub fn convert_transcribe_json(json_string: &str) -> Result<String, Error> {
+// Group the words of a Transcribe JSON transcript into speaker turns, with the
+// start and end time of each turn.
+pub fn transcribe_json_turns(json_string: &str) -> Result<Vec<SpeakerTurn>, Error> {
     let v: Value = serde_json::from_str(json_string).with_context(|| "Failed to parse JSON")?;
 
-    let mut final_transcript = String::new();
-    let mut current_speaker: Option<String> = None;
-    let mut current_text = String::new();
+    let mut turns: Vec<SpeakerTurn> = Vec::new();
+    let mut current: Option<SpeakerTurn> = None;
+
+    let items = v["results"]["items"].as_array().ok_or_else(|| {
+        anyhow!("The transcript has no results.items array; it may be empty or not a Transcribe transcript")
+    })?;
 
-    for item in v["results"]["items"].as_array().unwrap() {
-        match item["type"].as_str().unwrap() {
+    for item in items {
+        let item_type = item["type"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Missing transcript item 'type' data"))?;
+        match item_type {
             "pronunciation" => {
-                let content = item["alternatives"][0]["content"]
+                let alternative = best_alternative(item);
+                let content = alternative["content"]
                     .as_str()
                     .ok_or_else(|| anyhow!("Missing pronunciation content data"))?;
-                let speaker_label = item["speaker_label"]
-                    .as_str()
-                    .ok_or_else(|| anyhow!("Missing 'speaker_label' data"))?;
-
-                if let Some(current_speaker_label) = current_speaker.as_ref() {
-                    if current_speaker_label != speaker_label {
-                        if !current_text.is_empty() {
-                            final_transcript.push_str(&format!(
-                                "{}: {}\n",
-                                current_speaker_label,
-                                current_text.trim()
-                            ));
-                        }
-                        current_speaker = Some(speaker_label.to_string());
-                        current_text = content.to_string();
-                    } else {
-                        current_text.push(' ');
-                        current_text.push_str(content);
+                // Jobs run without speaker labels have none; their words make
+                // up a single unlabeled turn
+                let speaker_label = item["speaker_label"].as_str().unwrap_or_default();
+                let start = item_time(item, "start_time");
+                let end = item_time(item, "end_time");
+
+                match current.as_mut() {
+                    Some(turn) if turn.speaker == speaker_label => {
+                        turn.text.push(' ');
+                        turn.text.push_str(content);
+                    }
+                    _ => {
+                        turns.extend(current.take());
+                        current = Some(SpeakerTurn {
+                            speaker: speaker_label.to_string(),
+                            ..SpeakerTurn::unlabeled(content)
+                        });
+                    }
+                }
+                if let Some(turn) = current.as_mut() {
+                    turn.start = turn.start.or(start);
+                    turn.end = end.or(turn.end);
+                    if let (Some(start), Some(end)) = (start, end) {
+                        turn.words.push(TimedWord {
+                            text: content.to_string(),
+                            start,
+                            end,
+                            confidence: confidence(alternative),
+                        });
                     }
-                } else {
-                    current_speaker = Some(speaker_label.to_string());
-                    current_text = content.to_string();
                 }
             }
             "punctuation" => {
-                let content = item["alternatives"][0]["content"]
+                let content = best_alternative(item)["content"]
                     .as_str()
                     .ok_or_else(|| anyhow!("Missing punctuation content data"))?;
-                current_text.push_str(content);
+                if let Some(turn) = current.as_mut() {
+                    turn.text.push_str(content);
+                    if let Some(word) = turn.words.last_mut() {
+                        word.text.push_str(content);
+                    }
+                }
             }
             _ => {}
         }
     }
+    turns.extend(current);
+
+    for turn in &mut turns {
+        turn.text = turn.text.trim().to_string();
+    }
+
+    Ok(turns)
+}
+
+// The alternative transcriptions of each segment of a Transcribe JSON
+// transcript from a job run with ShowAlternatives, at most `max` per segment,
+// most likely first. Without alternatives there are no segments.
+pub fn transcribe_json_alternatives(
+    json_string: &str,
+    max: usize,
+) -> Result<Vec<TranscriptSegment>, Error> {
+    let v: Value = serde_json::from_str(json_string).with_context(|| "Failed to parse JSON")?;
+    let Some(segments) = v["results"]["segments"].as_array() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(segments
+        .iter()
+        .map(|segment| TranscriptSegment {
+            start: item_time(segment, "start_time"),
+            end: item_time(segment, "end_time"),
+            alternatives: segment["alternatives"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|alternative| alternative["transcript"].as_str())
+                .take(max)
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect())
+}
+
+// Transcribe reports item times in seconds, as strings, e.g. "1.25"
+fn item_time(item: &Value, key: &str) -> Option<f64> {
+    item[key].as_str().and_then(|time| time.parse().ok())
+}
+
+// The highest-confidence alternative of a transcript item. Transcribe reports
+// confidence as a string, e.g. "0.998"; ties and unparseable values keep the
+// earlier alternative, so single-alternative items always use index 0.
+fn best_alternative(item: &Value) -> &Value {
+    let confidence = |alternative: &Value| confidence(alternative).unwrap_or(0.0);
+
+    item["alternatives"]
+        .as_array()
+        .and_then(|alternatives| {
+            alternatives.iter().reduce(|best, alternative| {
+                if confidence(alternative) > confidence(best) {
+                    alternative
+                } else {
+                    best
+                }
+            })
+        })
+        .unwrap_or(&Value::Null)
+}
+
+fn confidence(alternative: &Value) -> Option<f64> {
+    alternative["confidence"]
+        .as_str()
+        .and_then(|confidence| confidence.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve, MockHttp, MockRequest, MockResponse, TempDir};
+    use crate::transcript::speaker_turns;
+
+    const TRANSCRIPT: &str = r#"{"results": {"items": [
+        {"type": "pronunciation", "start_time": "0.0", "end_time": "0.5", "speaker_label": "spk_0",
+         "alternatives": [{"confidence": "0.99", "content": "Hello"}]},
+        {"type": "punctuation", "alternatives": [{"confidence": "0.0", "content": "."}]}
+    ]}}"#;
+
+    // A GetTranscriptionJob/StartTranscriptionJob response for `request`'s job
+    fn job_response(request: &MockRequest, status: &str, transcript_url: &str) -> MockResponse {
+        MockResponse::ok(
+            serde_json::json!({
+                "TranscriptionJob": {
+                    "TranscriptionJobName": request.json()["TranscriptionJobName"],
+                    "TranscriptionJobStatus": status,
+                    "LanguageCode": "en-US",
+                    "Transcript": {"TranscriptFileUri": transcript_url},
+                }
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn transcribe_audio_returns_the_transcript_of_a_completed_job() {
+        let transcript_url = serve(vec![(200, TRANSCRIPT.to_string())]);
+        let mock = MockHttp::new(move |request| match request.operation() {
+            "DeleteTranscriptionJob" => MockResponse::ok("{}"),
+            _ => job_response(request, "COMPLETED", &transcript_url),
+        });
+        let settings = DistillSettings::default();
+        let client = client(&mock.sdk_config().await, &settings);
+        let in_flight = InFlight::default();
+
+        let transcription = transcribe_audio(
+            &client,
+            &MediaFormat::Mp3,
+            "s3://meetings/standup.mp3",
+            &mut Progress::new("", true),
+            &Options::default(),
+            &settings,
+            &in_flight,
+        )
+        .await
+        .unwrap();
+
+        assert!(transcription.job_name.starts_with("transcription-"));
+        assert_eq!(transcription.language_code.as_deref(), Some("en-US"));
+        assert_eq!(transcription.turns.len(), 1);
+        assert_eq!(transcription.turns[0].speaker, "spk_0");
+        assert_eq!(transcription.turns[0].text, "Hello.");
+
+        let operations: Vec<String> = mock
+            .requests()
+            .iter()
+            .map(|request| request.operation().to_string())
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                "StartTranscriptionJob",
+                "GetTranscriptionJob",
+                "DeleteTranscriptionJob"
+            ]
+        );
+        // The deleted job is no longer in flight
+        assert!(in_flight.jobs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn language_decision_accepts_without_a_threshold() {
+        assert_eq!(
+            language_decision(Some(0.1), None, true),
+            LanguageDecision::Accept
+        );
+        assert_eq!(
+            language_decision(None, None, false),
+            LanguageDecision::Accept
+        );
+    }
+
+    #[test]
+    fn language_decision_accepts_a_confidence_at_or_above_the_threshold() {
+        for has_fallback in [false, true] {
+            assert_eq!(
+                language_decision(Some(0.8), Some(0.8), has_fallback),
+                LanguageDecision::Accept
+            );
+            assert_eq!(
+                language_decision(Some(0.95), Some(0.8), has_fallback),
+                LanguageDecision::Accept
+            );
+        }
+    }
+
+    #[test]
+    fn language_decision_warns_below_the_threshold_without_a_fallback() {
+        assert_eq!(
+            language_decision(Some(0.79), Some(0.8), false),
+            LanguageDecision::Warn
+        );
+        // A missing score counts as no confidence
+        assert_eq!(
+            language_decision(None, Some(0.8), false),
+            LanguageDecision::Warn
+        );
+    }
+
+    #[test]
+    fn language_decision_falls_back_below_the_threshold_with_a_fallback() {
+        assert_eq!(
+            language_decision(Some(0.79), Some(0.8), true),
+            LanguageDecision::Fallback
+        );
+        assert_eq!(
+            language_decision(None, Some(0.8), true),
+            LanguageDecision::Fallback
+        );
+    }
+
+    #[test]
+    fn check_input_file_rejects_an_empty_file() {
+        let dir = TempDir::new();
+        let path = dir.join("meeting.mp3");
+        std::fs::write(&path, b"").unwrap();
+
+        let err = check_input_file(&path, None, None).unwrap_err();
+
+        assert!(err.to_string().contains("is empty"), "{}", err);
+    }
+
+    #[test]
+    fn check_input_file_rejects_a_junk_file_with_an_audio_extension() {
+        let dir = TempDir::new();
+        let path = dir.join("meeting.mp3");
+        std::fs::write(&path, b"not audio").unwrap();
+
+        let err = check_input_file(&path, None, Some(InputMediaFormat::Mp3)).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("not a recognizable audio or video file"),
+            "{}",
+            err
+        );
+        // Unless the format is given
+        assert_eq!(
+            check_input_file(&path, Some(InputMediaFormat::Mp3), None).unwrap(),
+            MediaFormat::Mp3
+        );
+    }
+
+    #[test]
+    fn check_input_file_rejects_an_unrecognizable_file() {
+        let dir = TempDir::new();
+        let path = dir.join("meeting");
+        std::fs::write(&path, b"not audio").unwrap();
+
+        let err = check_input_file(&path, None, None).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("not a recognizable audio or video file"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn detect_media_format_uses_the_default_when_nothing_else_matches() {
+        let dir = TempDir::new();
+        let path = dir.join("export");
+        std::fs::write(&path, b"unrecognizable").unwrap();
+
+        let media_format = detect_media_format(&path, Some(InputMediaFormat::Flac)).unwrap();
+
+        assert_eq!(media_format, MediaFormat::Flac);
+        assert!(detect_media_format(&path, None).is_err());
+    }
+
+    #[test]
+    fn detect_media_format_uses_the_default_for_an_unsupported_type() {
+        let dir = TempDir::new();
+        let path = dir.join("export");
+        // A PNG signature: recognized, but not a format Transcribe accepts
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        let media_format = detect_media_format(&path, Some(InputMediaFormat::Flac)).unwrap();
+
+        assert_eq!(media_format, MediaFormat::Flac);
+        let err = detect_media_format(&path, None).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unsupported media format: image/png"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn detect_media_format_prefers_the_extension_over_the_default() {
+        let dir = TempDir::new();
+        let path = dir.join("export.wav");
+        std::fs::write(&path, b"unrecognizable").unwrap();
+
+        let media_format = detect_media_format(&path, Some(InputMediaFormat::Flac)).unwrap();
+
+        assert_eq!(media_format, MediaFormat::Wav);
+    }
+
+    // A WAV header for `data_size` bytes of 8 kHz, 16-bit mono audio (16,000
+    // bytes a second), without the audio itself
+    fn wav_header(data_size: u32) -> Vec<u8> {
+        let mut header = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&[1, 0, 1, 0]);
+        header.extend_from_slice(&8_000u32.to_le_bytes());
+        header.extend_from_slice(&16_000u32.to_le_bytes());
+        header.extend_from_slice(&[2, 0, 16, 0]);
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn limits_warning_warns_about_audio_over_four_hours() {
+        let dir = TempDir::new();
+        let path = dir.join("offsite.wav");
+        // Five hours
+        std::fs::write(&path, wav_header(5 * 60 * 60 * 16_000)).unwrap();
+
+        let warning = limits_warning(&path, &MediaFormat::Wav).unwrap();
+
+        assert!(
+            warning.contains("is at least 5.0 hours long"),
+            "{}",
+            warning
+        );
+        assert!(warning.contains("ffmpeg -i"), "{}", warning);
+        // Still a file to transcribe: the upload goes ahead
+        assert_eq!(
+            check_input_file(&path, Some(InputMediaFormat::Wav), None).unwrap(),
+            MediaFormat::Wav
+        );
+    }
+
+    #[test]
+    fn limits_warning_is_none_within_the_limits() {
+        let dir = TempDir::new();
+        let path = dir.join("standup.wav");
+        // One hour
+        std::fs::write(&path, wav_header(60 * 60 * 16_000)).unwrap();
+
+        assert_eq!(limits_warning(&path, &MediaFormat::Wav), None);
+        // Other formats' length isn't known
+        assert_eq!(limits_warning(&path, &MediaFormat::Flac), None);
+    }
+
+    #[test]
+    fn job_name_adds_the_prefix() {
+        assert_eq!(job_name(None, "1234"), "transcription-1234");
+        assert_eq!(
+            job_name(Some("myteam"), "1234"),
+            "myteam-transcription-1234"
+        );
+    }
+
+    #[test]
+    fn validate_job_prefix_rejects_characters_transcribe_does_not_allow() {
+        assert!(validate_job_prefix("my-team_2.0").is_ok());
 
-    if let Some(speaker_label) = current_speaker {
-        if !current_text.is_empty() {
-            final_transcript.push_str(&format!("{}: {}\n", speaker_label, current_text.trim()));
+        for prefix in ["my team", "team/ops", "équipe", "a:b"] {
+            let err = validate_job_prefix(prefix).unwrap_err();
+            assert!(err.to_string().contains("Invalid character"), "{}", err);
         }
+        assert!(validate_job_prefix("").is_err());
+    }
+
+    #[test]
+    fn validate_job_prefix_keeps_job_names_within_200_characters() {
+        let id = Uuid::new_v4().to_string();
+        let longest = "a".repeat(149);
+
+        assert!(validate_job_prefix(&longest).is_ok());
+        assert_eq!(job_name(Some(&longest), &id).len(), 200);
+
+        let err = validate_job_prefix(&"a".repeat(150)).unwrap_err();
+        assert!(
+            err.to_string().contains("at most 149 characters"),
+            "{}",
+            err
+        );
+    }
+
+    // A transcript from a job run with ShowAlternatives: items keep several
+    // alternatives, not always best first, and segments have alternative
+    // transcriptions
+    const ALTERNATIVES_TRANSCRIPT: &str = r#"{"results": {
+        "items": [
+            {"type": "pronunciation", "start_time": "0.0", "end_time": "0.4", "speaker_label": "spk_0",
+             "alternatives": [{"confidence": "0.41", "content": "Chip"}, {"confidence": "0.58", "content": "Ship"}]},
+            {"type": "pronunciation", "start_time": "0.5", "end_time": "0.9", "speaker_label": "spk_0",
+             "alternatives": [{"confidence": "0.97", "content": "it"}]},
+            {"type": "punctuation", "alternatives": [{"confidence": "0.0", "content": "."}]}
+        ],
+        "segments": [
+            {"start_time": "0.0", "end_time": "0.9", "alternatives": [
+                {"transcript": "Ship it.", "items": []},
+                {"transcript": "Chip it.", "items": []},
+                {"transcript": "Sip it.", "items": []}
+            ]}
+        ]
+    }}"#;
+
+    #[test]
+    fn transcribe_json_turns_uses_the_most_confident_alternative() {
+        let turns = transcribe_json_turns(ALTERNATIVES_TRANSCRIPT).unwrap();
+
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].text, "Ship it.");
+        assert_eq!(turns[0].words[0].text, "Ship");
+        assert_eq!(turns[0].words[0].confidence, Some(0.58));
+    }
+
+    #[test]
+    fn transcribe_json_alternatives_keeps_the_top_alternatives_of_each_segment() {
+        let segments = transcribe_json_alternatives(ALTERNATIVES_TRANSCRIPT, 2).unwrap();
+
+        assert_eq!(
+            segments,
+            vec![TranscriptSegment {
+                start: Some(0.0),
+                end: Some(0.9),
+                alternatives: vec!["Ship it.".to_string(), "Chip it.".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn transcribe_json_alternatives_is_empty_without_segments() {
+        assert_eq!(transcribe_json_alternatives(TRANSCRIPT, 2).unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn keep_alternatives_asks_transcribe_for_them() {
+        let transcript_url = serve(vec![(200, ALTERNATIVES_TRANSCRIPT.to_string())]);
+        let mock = MockHttp::new(move |request| match request.operation() {
+            "DeleteTranscriptionJob" => MockResponse::ok("{}"),
+            _ => job_response(request, "COMPLETED", &transcript_url),
+        });
+        let settings = DistillSettings::default();
+        let client = client(&mock.sdk_config().await, &settings);
+        let opts = Options {
+            keep_alternatives: Some(3),
+            ..Options::default()
+        };
+
+        let transcription = transcribe_audio(
+            &client,
+            &MediaFormat::Mp3,
+            "s3://meetings/standup.mp3",
+            &mut Progress::new("", true),
+            &opts,
+            &settings,
+            &InFlight::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transcription.alternatives[0].alternatives.len(), 3);
+        let start = &mock.requests()[0];
+        assert_eq!(start.json()["Settings"]["ShowAlternatives"], true);
+        assert_eq!(start.json()["Settings"]["MaxAlternatives"], 3);
+    }
+
+    #[tokio::test]
+    async fn client_honors_the_transcribe_endpoint() {
+        let mock = MockHttp::new(|_| MockResponse::ok("{}"));
+        let mut settings = DistillSettings::default();
+        settings.transcribe.endpoint_url = Some("http://localhost:4566".to_string());
+        let client = client(&mock.sdk_config().await, &settings);
+
+        delete_job(&client, "transcription-1").await;
+
+        assert!(mock.requests()[0].uri.starts_with("http://localhost:4566"));
+    }
+
+    // A mock whose GetTranscriptionJob calls report `statuses` in turn, and
+    // the last one from then on
+    fn job_statuses(statuses: &'static [&'static str]) -> MockHttp {
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        MockHttp::new(move |request| match request.operation() {
+            "StartTranscriptionJob" => job_response(request, "QUEUED", ""),
+            "GetTranscriptionJob" => {
+                let poll = polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let status = statuses[poll.min(statuses.len() - 1)];
+                job_response(request, status, "")
+            }
+            _ => MockResponse::ok("{}"),
+        })
+    }
+
+    fn operations(mock: &MockHttp) -> Vec<String> {
+        mock.requests()
+            .iter()
+            .map(|request| request.operation().to_string())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_job_keeps_polling_a_queued_job_until_it_completes() {
+        let mock = job_statuses(&["QUEUED", "QUEUED", "IN_PROGRESS", "COMPLETED"]);
+        let client = client(&mock.sdk_config().await, &DistillSettings::default());
+
+        let job_details = wait_for_job(
+            &client,
+            "transcription-1",
+            &TranscribeSettings::default(),
+            &mut Progress::new("", true),
+        )
+        .await
+        .unwrap();
+
+        assert!(job_completed(&job_details));
+        assert_eq!(operations(&mock), vec!["GetTranscriptionJob"; 4]);
     }
 
-    Ok(final_transcript)
+    #[tokio::test(start_paused = true)]
+    async fn a_job_that_never_finishes_is_abandoned_and_deleted() {
+        let mock = job_statuses(&["QUEUED"]);
+        let mut settings = DistillSettings::default();
+        settings.transcribe.max_polls = 3;
+        let client = client(&mock.sdk_config().await, &settings);
+        let in_flight = InFlight::default();
+
+        let err = transcribe_audio(
+            &client,
+            &MediaFormat::Mp3,
+            "s3://meetings/standup.mp3",
+            &mut Progress::new("", true),
+            &Options::default(),
+            &settings,
+            &in_flight,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("did not finish within 3 status checks"));
+        assert_eq!(
+            operations(&mock),
+            vec![
+                "StartTranscriptionJob",
+                "GetTranscriptionJob",
+                "GetTranscriptionJob",
+                "GetTranscriptionJob",
+                "DeleteTranscriptionJob"
+            ]
+        );
+        assert!(in_flight.jobs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn speaker_count_warning_compares_the_detected_speakers_with_the_expected() {
+        let turns = speaker_turns("spk_0: Hi\nspk_1: Hello\nspk_0: Let's start\n");
+
+        assert_eq!(speaker_count_warning(&turns, None), None);
+        assert_eq!(speaker_count_warning(&turns, Some(2)), None);
+        assert_eq!(
+            speaker_count_warning(&turns, Some(3)).unwrap(),
+            "Expected 3 speakers but Amazon Transcribe detected 2. Speaker labels may be inaccurate."
+        );
+        // Unlabeled speech isn't a speaker
+        let unlabeled = speaker_turns("Hi, hello, let's start\n");
+        assert!(speaker_count_warning(&unlabeled, Some(2))
+            .unwrap()
+            .contains("detected 0"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fetch_transcript_retries_a_failed_download() {
+        let url = serve(vec![
+            (503, String::new()),
+            (500, String::new()),
+            (200, TRANSCRIPT.to_string()),
+        ]);
+
+        let body = fetch_transcript(&format!("{}/transcript.json", url))
+            .await
+            .unwrap();
+
+        assert_eq!(body, TRANSCRIPT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fetch_transcript_reports_the_uri_once_it_gives_up() {
+        let transcript_uri = format!("{}/transcript.json", serve(vec![(503, String::new())]));
+
+        let err = fetch_transcript(&transcript_uri).await.unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("after 4 attempts"));
+        assert!(message.ends_with(&transcript_uri));
+    }
+
+    #[test]
+    fn transcribe_json_turns_fails_cleanly_without_items() {
+        for json in [
+            r#"{"results": {"transcripts": []}}"#,
+            r#"{"message": "Access Denied"}"#,
+        ] {
+            let err = transcribe_json_turns(json).unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with("The transcript has no results.items array"));
+        }
+
+        let err = transcribe_json_turns("<Error>AccessDenied</Error>").unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse JSON");
+    }
 }
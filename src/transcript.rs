@@ -0,0 +1,279 @@
+// What to do when a transcript is shorter than `transcribe.min_transcript_words`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum EmptyTranscriptAction {
+    // Stop with an error
+    Abort,
+    // Write the transcription without summarizing it
+    Skip,
+    // Summarize anyway
+    #[default]
+    Proceed,
+}
+
+// A run of consecutive speech from a single speaker. Start and end times, in
+// seconds, and the timed words are only known for turns built from
+// Transcribe's JSON output.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SpeakerTurn {
+    pub speaker: String,
+    pub text: String,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub words: Vec<TimedWord>,
+}
+
+// A spoken word with any punctuation that follows it, e.g. "Hello,", and
+// Transcribe's confidence (0-1) in it, if reported
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimedWord {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+    pub confidence: Option<f64>,
+}
+
+// A stretch of the recording Transcribe offers alternative transcriptions
+// of, most likely first, with its start and end time in seconds
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TranscriptSegment {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub alternatives: Vec<String>,
+}
+
+impl SpeakerTurn {
+    // A turn without a speaker label or timings
+    pub fn unlabeled(text: &str) -> Self {
+        Self {
+            speaker: String::new(),
+            text: text.to_string(),
+            start: None,
+            end: None,
+            words: Vec::new(),
+        }
+    }
+}
+
+// Split a transcription in the "spk_0: text" line format produced by
+// `transcript_text` into speaker turns. Lines without a speaker label
+// are folded into the preceding turn.
+pub fn speaker_turns(transcription: &str) -> Vec<SpeakerTurn> {
+    let mut turns: Vec<SpeakerTurn> = Vec::new();
+
+    for line in transcription.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_once(": ") {
+            Some((speaker, text)) if is_speaker_label(speaker) => turns.push(SpeakerTurn {
+                speaker: speaker.to_string(),
+                ..SpeakerTurn::unlabeled(text.trim())
+            }),
+            _ => match turns.last_mut() {
+                Some(turn) => {
+                    turn.text.push(' ');
+                    turn.text.push_str(line.trim());
+                }
+                None => turns.push(SpeakerTurn::unlabeled(line.trim())),
+            },
+        }
+    }
+
+    turns
+}
+
+// Speaker labels are single tokens such as "spk_0"
+fn is_speaker_label(label: &str) -> bool {
+    !label.is_empty() && !label.contains(char::is_whitespace)
+}
+
+// The "spk_0: text" line format, one line per turn
+pub fn transcript_text(turns: &[SpeakerTurn]) -> String {
+    turns
+        .iter()
+        .map(|turn| match turn.speaker.as_str() {
+            "" => format!("{}\n", turn.text),
+            speaker => format!("{}: {}\n", speaker, turn.text),
+        })
+        .collect()
+}
+
+// The turns with words Transcribe is less confident about than `threshold`
+// marked for review, e.g. "[Hello?],". Turns without timed words are kept as
+// they are.
+pub fn marked_turns(turns: &[SpeakerTurn], threshold: f64) -> Vec<SpeakerTurn> {
+    turns
+        .iter()
+        .map(|turn| {
+            if turn.words.is_empty() {
+                return turn.clone();
+            }
+            let words: Vec<String> = turn
+                .words
+                .iter()
+                .map(|word| match word.confidence {
+                    Some(confidence) if confidence < threshold => {
+                        let spoken = word
+                            .text
+                            .trim_end_matches(|c: char| c.is_ascii_punctuation());
+                        format!("[{}?]{}", spoken, &word.text[spoken.len()..])
+                    }
+                    _ => word.text.clone(),
+                })
+                .collect();
+            SpeakerTurn {
+                text: words.join(" "),
+                ..turn.clone()
+            }
+        })
+        .collect()
+}
+
+// Split turns into caption-sized segments spanning at most `max_seconds`.
+// Segments never cross a speaker change. Turns without timed words are kept
+// whole.
+pub fn caption_segments(turns: &[SpeakerTurn], max_seconds: f64) -> Vec<SpeakerTurn> {
+    let mut segments = Vec::new();
+
+    for turn in turns {
+        if turn.words.is_empty() {
+            segments.push(turn.clone());
+            continue;
+        }
+
+        let mut words: Vec<TimedWord> = Vec::new();
+        for word in &turn.words {
+            if words
+                .first()
+                .is_some_and(|first| word.end - first.start > max_seconds)
+            {
+                segments.push(segment(&turn.speaker, std::mem::take(&mut words)));
+            }
+            words.push(word.clone());
+        }
+        segments.push(segment(&turn.speaker, words));
+    }
+
+    segments
+}
+
+// A turn made of the given (non-empty) words
+fn segment(speaker: &str, words: Vec<TimedWord>) -> SpeakerTurn {
+    let text: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+
+    SpeakerTurn {
+        speaker: speaker.to_string(),
+        text: text.join(" "),
+        start: words.first().map(|word| word.start),
+        end: words.last().map(|word| word.end),
+        words,
+    }
+}
+
+// Number of distinct speakers
+pub fn speaker_count(turns: &[SpeakerTurn]) -> usize {
+    let mut speakers: Vec<&str> = turns
+        .iter()
+        .map(|turn| turn.speaker.as_str())
+        .filter(|speaker| !speaker.is_empty())
+        .collect();
+    speakers.sort();
+    speakers.dedup();
+
+    speakers.len()
+}
+
+// Number of spoken words in a transcription, not counting speaker labels
+pub fn word_count(transcription: &str) -> usize {
+    speaker_turns(transcription)
+        .iter()
+        .map(|turn| turn.text.split_whitespace().count())
+        .sum()
+}
+
+// How long each speaker talked, in seconds, in order of first appearance.
+// Turns without a speaker label or timings aren't counted.
+pub fn talk_time(turns: &[SpeakerTurn]) -> Vec<(&str, f64)> {
+    let mut speakers: Vec<(&str, f64)> = Vec::new();
+    for turn in turns.iter().filter(|turn| !turn.speaker.is_empty()) {
+        let (Some(start), Some(end)) = (turn.start, turn.end) else {
+            continue;
+        };
+        let seconds = (end - start).max(0.0);
+        match speakers
+            .iter_mut()
+            .find(|(speaker, _)| *speaker == turn.speaker)
+        {
+            Some((_, total)) => *total += seconds,
+            None => speakers.push((&turn.speaker, seconds)),
+        }
+    }
+
+    speakers
+}
+
+// E.g. "Talk time:\n- spk_0: 12m 30s (45%)\n- spk_1: 15m 17s (55%)". Empty
+// when the transcript has no timed speaker turns.
+pub fn talk_time_text(turns: &[SpeakerTurn]) -> String {
+    let speakers = talk_time(turns);
+    let total: f64 = speakers.iter().map(|(_, seconds)| seconds).sum();
+    if total <= 0.0 {
+        return String::new();
+    }
+
+    let lines: Vec<String> = speakers
+        .iter()
+        .map(|(speaker, seconds)| {
+            format!(
+                "- {}: {} ({:.0}%)",
+                speaker,
+                duration_text(*seconds),
+                seconds / total * 100.0
+            )
+        })
+        .collect();
+    format!("Talk time:\n{}", lines.join("\n"))
+}
+
+// E.g. "45s", "12m 30s" or "1h 02m 05s"
+fn duration_text(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, confidence: f64) -> TimedWord {
+        TimedWord {
+            text: text.to_string(),
+            start: 0.0,
+            end: 0.5,
+            confidence: Some(confidence),
+        }
+    }
+
+    #[test]
+    fn marked_turns_marks_words_below_the_threshold() {
+        let turns = [
+            SpeakerTurn {
+                speaker: "spk_0".to_string(),
+                words: vec![word("Ship", 0.4), word("it,", 0.99), word("Ana.", 0.2)],
+                ..SpeakerTurn::unlabeled("Ship it, Ana.")
+            },
+            // Without timed words, e.g. from a plain text transcript
+            SpeakerTurn::unlabeled("Agreed."),
+        ];
+
+        let marked = marked_turns(&turns, 0.5);
+
+        assert_eq!(marked[0].text, "[Ship?] it, [Ana?].");
+        assert_eq!(marked[0].speaker, "spk_0");
+        assert_eq!(marked[1].text, "Agreed.");
+    }
+}
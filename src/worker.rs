@@ -0,0 +1,286 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_sqs::Client;
+use serde::Deserialize;
+use tokio::task::JoinSet;
+
+use crate::{output, Distiller, Input, Options};
+
+// Long-poll for up to 20 seconds, the maximum SQS allows
+const WAIT_TIME_SECONDS: i32 = 20;
+// SQS returns at most 10 messages per receive
+const MAX_MESSAGES_PER_RECEIVE: usize = 10;
+
+// The parts of an S3 event notification the worker uses. S3 also sends an
+// "s3:TestEvent" message without records when notifications are configured.
+#[derive(Debug, Deserialize)]
+struct S3Event {
+    #[serde(rename = "Records", default)]
+    records: Vec<S3EventRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3EventRecord {
+    #[serde(rename = "eventName")]
+    event_name: String,
+    s3: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Entity {
+    bucket: S3Bucket,
+    object: S3Object,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Bucket {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Object {
+    key: String,
+}
+
+/// Long-poll the `worker.queue_url` SQS queue for S3 `ObjectCreated`
+/// notifications and distill each new recording, writing a Markdown summary
+/// back to its bucket under `worker.output_prefix`. A message is deleted once
+/// its recording is processed; on failure it is left on the queue to become
+/// visible again (or be redriven to a dead-letter queue). Runs until receiving
+/// from the queue fails.
+pub async fn run(distiller: Arc<Distiller>, client: Client, opts: Options) -> Result<()> {
+    let settings = distiller.settings().worker.clone();
+    let queue_url = settings
+        .queue_url
+        .clone()
+        .context("Worker mode requires worker.queue_url to be set in config.toml")?;
+    // Results written to the bucket root would look like new recordings
+    if settings.output_prefix.is_empty() {
+        bail!("worker.output_prefix must not be empty");
+    }
+    let concurrency = settings.concurrency.max(1);
+
+    println!("👷 Waiting for recordings on {}", queue_url);
+
+    let mut tasks = JoinSet::new();
+    loop {
+        // Make room for at least one more recording
+        while tasks.try_join_next().is_some() {}
+        if tasks.len() >= concurrency {
+            tasks.join_next().await;
+            continue;
+        }
+
+        let max_messages = (concurrency - tasks.len()).min(MAX_MESSAGES_PER_RECEIVE);
+        let resp = client
+            .receive_message()
+            .queue_url(&queue_url)
+            .max_number_of_messages(max_messages as i32)
+            .wait_time_seconds(WAIT_TIME_SECONDS)
+            .visibility_timeout(settings.visibility_timeout_secs)
+            .send()
+            .await
+            .context("Failed to receive messages from SQS")?;
+
+        for message in resp.messages() {
+            let (Some(body), Some(receipt_handle)) = (message.body(), message.receipt_handle())
+            else {
+                continue;
+            };
+            let body = body.to_string();
+            let receipt_handle = receipt_handle.to_string();
+            let distiller = Arc::clone(&distiller);
+            let client = client.clone();
+            let queue_url = queue_url.clone();
+            let output_prefix = settings.output_prefix.clone();
+            let visibility_timeout_secs = settings.visibility_timeout_secs;
+            let opts = opts.clone();
+
+            tasks.spawn(async move {
+                let processing = process_message(&distiller, &body, &output_prefix, opts);
+                let result = keep_hidden(
+                    &client,
+                    &queue_url,
+                    &receipt_handle,
+                    visibility_timeout_secs,
+                    processing,
+                )
+                .await;
+                if let Err(err) = result {
+                    eprintln!("❌ Leaving message on the queue for retry: {:#}", err);
+                    return;
+                }
+
+                if let Err(err) = client
+                    .delete_message()
+                    .queue_url(&queue_url)
+                    .receipt_handle(receipt_handle)
+                    .send()
+                    .await
+                {
+                    eprintln!("⚠️  Unable to delete processed message: {}", err);
+                }
+            });
+        }
+    }
+}
+
+// Drive `processing` to completion, extending the message's visibility
+// timeout every half timeout so other tasks and workers don't receive it again
+// while its recording is still being processed
+async fn keep_hidden<T>(
+    client: &Client,
+    queue_url: &str,
+    receipt_handle: &str,
+    visibility_timeout_secs: i32,
+    processing: impl Future<Output = T>,
+) -> T {
+    let period = Duration::from_secs((visibility_timeout_secs / 2).max(1) as u64);
+    let mut processing = std::pin::pin!(processing);
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        tokio::select! {
+            result = &mut processing => return result,
+            _ = ticks.tick() => {
+                if let Err(err) = client
+                    .change_message_visibility()
+                    .queue_url(queue_url)
+                    .receipt_handle(receipt_handle)
+                    .visibility_timeout(visibility_timeout_secs)
+                    .send()
+                    .await
+                {
+                    eprintln!("⚠️  Unable to extend the message's visibility timeout: {}", err);
+                }
+            }
+        }
+    }
+}
+
+// Distill every recording created in an S3 event notification
+async fn process_message(
+    distiller: &Distiller,
+    body: &str,
+    output_prefix: &str,
+    opts: Options,
+) -> Result<()> {
+    let event: S3Event =
+        serde_json::from_str(body).context("Message is not an S3 event notification")?;
+
+    for record in event.records {
+        if !record.event_name.starts_with("ObjectCreated:") {
+            continue;
+        }
+
+        let bucket = record.s3.bucket.name;
+        let key = decode_key(&record.s3.object.key);
+        // Guard against summaries triggering further work when the bucket's
+        // notifications aren't filtered
+        if key.starts_with(output_prefix) {
+            continue;
+        }
+
+        println!("🎙️  Processing s3://{}/{}", bucket, key);
+        let result = distiller
+            .run(
+                Input::S3 {
+                    bucket: bucket.clone(),
+                    key: key.clone(),
+                },
+                opts.clone(),
+            )
+            .await
+            .with_context(|| format!("Failed to process s3://{}/{}", bucket, key))?;
+
+        let output_key = format!("{}{}.md", output_prefix, key);
+        distiller
+            .put_text(
+                &bucket,
+                &output_key,
+                output::markdown_document(&result.summary, &result.turns),
+            )
+            .await?;
+        println!("💾 Summary written to s3://{}/{}", bucket, output_key);
+    }
+
+    Ok(())
+}
+
+// Object keys in S3 event notifications are URL-encoded, with spaces as "+"
+fn decode_key(key: &str) -> String {
+    let bytes = key.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockHttp, MockResponse};
+
+    #[test]
+    fn decode_key_decodes_plus_and_percent_escapes() {
+        assert_eq!(decode_key("team+sync%20notes.mp3"), "team sync notes.mp3");
+        assert_eq!(decode_key("caf%C3%A9.m4a"), "café.m4a");
+        // A "%" without two hex digits after it is kept as it is
+        assert_eq!(decode_key("100%"), "100%");
+        assert_eq!(decode_key("50%2"), "50%2");
+        assert_eq!(decode_key("a%zzb"), "a%zzb");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_hidden_extends_the_visibility_while_processing() {
+        let mock = MockHttp::new(|_| MockResponse::ok("{}"));
+        let client = Client::new(&mock.sdk_config().await);
+
+        // Four and a half minutes of processing with a two minute timeout
+        let result = keep_hidden(&client, "https://sqs/queue", "receipt-1", 120, async {
+            tokio::time::sleep(Duration::from_secs(270)).await;
+            "done"
+        })
+        .await;
+
+        assert_eq!(result, "done");
+        let requests = mock.requests();
+        // Extended after one, two, three and four minutes
+        assert_eq!(requests.len(), 4);
+        assert!(requests
+            .iter()
+            .all(|request| request.operation() == "ChangeMessageVisibility"));
+        assert_eq!(requests[0].json()["ReceiptHandle"], "receipt-1");
+        assert_eq!(requests[0].json()["VisibilityTimeout"], 120);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_hidden_leaves_quick_messages_alone() {
+        let mock = MockHttp::new(|_| MockResponse::ok("{}"));
+        let client = Client::new(&mock.sdk_config().await);
+
+        keep_hidden(&client, "https://sqs/queue", "receipt-1", 120, async {}).await;
+
+        assert!(mock.requests().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+// Runs the library's entry point end to end against mocked S3, Transcribe and
+// Bedrock endpoints
+
+// Shared with the unit tests, which use more of it
+#[allow(dead_code)]
+#[path = "../src/test_util.rs"]
+mod test_util;
+
+use std::path::Path;
+
+use distill_cli::{Distiller, Input, Options, Settings};
+
+use test_util::{mock_aws, transcript_json, TempDir};
+
+#[tokio::test]
+async fn run_uploads_transcribes_and_summarizes() {
+    let transcript = transcript_json(&[("spk_0", "Ship"), ("spk_0", "it."), ("spk_1", "Agreed.")]);
+    let mock = mock_aws(&transcript, "We agreed to ship it.");
+    let settings =
+        Settings::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap();
+
+    // An MP3 frame header is enough for the format to be detected
+    let dir = TempDir::new();
+    let audio = dir.join("standup.mp3");
+    std::fs::write(&audio, [0xFF, 0xFB, 0x90, 0x64, 0, 0, 0, 0]).unwrap();
+
+    let distiller = Distiller::new(mock.sdk_config().await, settings);
+    let result = distiller
+        .run(
+            Input::File(audio),
+            Options {
+                bucket: "meetings".to_string(),
+                write_output: false,
+                quiet: true,
+                ..Options::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result.s3_uri, "s3://meetings/standup.mp3");
+    assert!(result.transcription.contains("Ship it."));
+    assert!(result.transcription.contains("Agreed."));
+    assert_eq!(result.summary, "We agreed to ship it.");
+    assert_eq!(result.language_code.as_deref(), Some("en-US"));
+    assert!(result.job_name.unwrap().starts_with("transcription-"));
+    assert_eq!(
+        result.model_ids,
+        vec!["anthropic.claude-3-sonnet-20240229-v1:0"]
+    );
+
+    let requests = mock.requests();
+    let upload = requests
+        .iter()
+        .find(|request| request.method == "PUT")
+        .unwrap();
+    assert!(upload.uri.contains("standup.mp3"));
+    let operations: Vec<&str> = requests
+        .iter()
+        .map(|request| request.operation())
+        .filter(|operation| !operation.is_empty())
+        .collect();
+    assert_eq!(
+        operations,
+        vec![
+            "StartTranscriptionJob",
+            "GetTranscriptionJob",
+            "DeleteTranscriptionJob"
+        ]
+    );
+    // The job reads the audio from where it was uploaded
+    let start = requests
+        .iter()
+        .find(|request| request.operation() == "StartTranscriptionJob")
+        .unwrap();
+    assert_eq!(start.json()["Media"]["MediaFileUri"], result.s3_uri);
+}